@@ -0,0 +1,370 @@
+// Expression parser and evaluator for typed or pasted input
+use crate::rational::{apply_rational_op, Rational};
+use crate::CalcError;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+/// Evaluate an arithmetic expression such as "(2+3)*4".
+///
+/// Supports `+ - * / ^`, parentheses, unary minus, the display symbols
+/// `×`, `÷`, `−` and `√`, and the functions `sqrt`, `sqr`, `isqrt`, `min`,
+/// `max` and `round` (to a multiple).
+///
+/// `^` binds tighter than a leading minus, as on most calculators: `-3^2` is
+/// `-(3^2) = -9`, while `(-3)^2` is 9. A minus after `^` belongs to the
+/// exponent, so `2^-1` is 0.5.
+pub fn evaluate(input: &str) -> Result<f64, CalcError> {
+    evaluate_with(input, &|_| None)
+}
+
+/// `evaluate` with named values looked up through `resolve`
+pub fn evaluate_with(input: &str, resolve: &dyn Fn(&str) -> Option<f64>) -> Result<f64, CalcError> {
+    parse(input)?.eval(resolve)
+}
+
+/// Check that `input` tokenizes and parses, without evaluating it: catches
+/// unbalanced parentheses, stray operators and unknown characters, but not
+/// runtime errors like division by zero or unknown function names.
+pub fn validate_expression(input: &str) -> Result<(), CalcError> {
+    parse(input).map(|_| ())
+}
+
+pub(crate) fn parse(input: &str) -> Result<Expr, CalcError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.expr()?;
+    if parser.pos != parser.tokens.len() {
+        return Err(CalcError::Syntax);
+    }
+    Ok(expr)
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '0'..='9' | '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                // Exponent only when digits follow, so "2e" stays an error
+                if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                    let mut j = i + 1;
+                    if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                        j += 1;
+                    }
+                    if j < chars.len() && chars[j].is_ascii_digit() {
+                        i = j;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                let value = text.parse::<f64>().map_err(|_| CalcError::Syntax)?;
+                tokens.push(Token::Num(value));
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().collect();
+                tokens.push(Token::Ident(name.to_lowercase()));
+            }
+            '\u{221A}' => {
+                tokens.push(Token::Ident("sqrt".to_string()));
+                i += 1;
+            }
+            '+' | '-' | '*' | '/' | '^' => {
+                tokens.push(Token::Op(c));
+                i += 1;
+            }
+            '\u{2212}' => {
+                tokens.push(Token::Op('-'));
+                i += 1;
+            }
+            '\u{00D7}' => {
+                tokens.push(Token::Op('*'));
+                i += 1;
+            }
+            '\u{00F7}' => {
+                tokens.push(Token::Op('/'));
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            _ => return Err(CalcError::Syntax),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_op(&mut self, ops: &[char]) -> Option<char> {
+        match self.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, CalcError> {
+        let mut lhs = self.term()?;
+        while let Some(op) = self.eat_op(&['+', '-']) {
+            let rhs = self.term()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, CalcError> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.eat_op(&['*', '/']) {
+            let rhs = self.unary()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    // unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<Expr, CalcError> {
+        match self.eat_op(&['-', '+']) {
+            Some('-') => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Some(_) => self.unary(),
+            None => self.power(),
+        }
+    }
+
+    // power := primary ('^' unary)?   (right-associative)
+    fn power(&mut self) -> Result<Expr, CalcError> {
+        let base = self.primary()?;
+        if self.eat_op(&['^']).is_some() {
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    // primary := number | ident | ident '(' args ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<Expr, CalcError> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Ident(name)) => {
+                if self.peek() != Some(&Token::LParen) {
+                    return Ok(Expr::Var(name));
+                }
+                self.pos += 1;
+                let mut args = vec![self.expr()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                    args.push(self.expr()?);
+                }
+                self.expect_rparen()?;
+                Ok(Expr::Call(name, args))
+            }
+            Some(Token::LParen) => {
+                let inner = self.expr()?;
+                self.expect_rparen()?;
+                Ok(inner)
+            }
+            _ => Err(CalcError::Syntax),
+        }
+    }
+
+    fn expect_rparen(&mut self) -> Result<(), CalcError> {
+        match self.next() {
+            Some(Token::RParen) => Ok(()),
+            _ => Err(CalcError::Syntax),
+        }
+    }
+}
+
+impl Expr {
+    pub(crate) fn eval(&self, resolve: &dyn Fn(&str) -> Option<f64>) -> Result<f64, CalcError> {
+        match self {
+            Expr::Num(n) => Ok(*n),
+            Expr::Var(name) => resolve(name).ok_or(CalcError::Syntax),
+            Expr::Neg(inner) => Ok(-inner.eval(resolve)?),
+            Expr::Binary(op, lhs, rhs) => crate::apply_op(lhs.eval(resolve)?, *op, rhs.eval(resolve)?),
+            Expr::Call(name, args) => {
+                let values = args
+                    .iter()
+                    .map(|arg| arg.eval(resolve))
+                    .collect::<Result<Vec<_>, _>>()?;
+                call(name, &values)
+            }
+        }
+    }
+
+    /// Exact evaluation for rational mode. Only integer literals and
+    /// `+ - * /` qualify; anything else is `InvalidInput` so the caller can
+    /// fall back to floating point.
+    pub(crate) fn eval_rational(&self) -> Result<Rational, CalcError> {
+        match self {
+            Expr::Num(n) if n.fract() == 0.0 && n.abs() < 9_007_199_254_740_992.0 => {
+                Ok(Rational::from_integer(*n as i64))
+            }
+            Expr::Neg(inner) => Rational::from_integer(0).checked_sub(inner.eval_rational()?),
+            Expr::Binary(op @ ('+' | '-' | '*' | '/'), lhs, rhs) => {
+                apply_rational_op(lhs.eval_rational()?, *op, rhs.eval_rational()?)
+            }
+            _ => Err(CalcError::InvalidInput),
+        }
+    }
+}
+
+fn call(name: &str, args: &[f64]) -> Result<f64, CalcError> {
+    match (name, args) {
+        ("sqrt", &[x]) => {
+            if x < 0.0 {
+                Err(CalcError::InvalidInput)
+            } else {
+                Ok(x.sqrt())
+            }
+        }
+        ("sqr", &[x]) => {
+            let result = x * x;
+            if result.is_finite() {
+                Ok(result)
+            } else {
+                Err(CalcError::Overflow)
+            }
+        }
+        ("min", &[a, b]) => Ok(a.min(b)),
+        ("max", &[a, b]) => Ok(a.max(b)),
+        ("round", &[a, b]) => crate::round_to_multiple(a, b).ok_or(CalcError::InvalidInput),
+        ("isqrt", &[x]) => {
+            if x < 0.0 || x.fract() != 0.0 || x > u64::MAX as f64 {
+                Err(CalcError::InvalidInput)
+            } else {
+                Ok(crate::isqrt(x as u64) as f64)
+            }
+        }
+        _ => Err(CalcError::Syntax),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_precedence() {
+        assert_eq!(evaluate("2 + 3 * 4"), Ok(14.0));
+        assert_eq!(evaluate("(2+3)*4"), Ok(20.0));
+        assert_eq!(evaluate("10 - 4 - 3"), Ok(3.0));
+        assert_eq!(evaluate("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_eq!(evaluate("1.5e2 / 3"), Ok(50.0));
+    }
+
+    #[test]
+    fn test_validate_expression() {
+        assert_eq!(validate_expression("(2 + 3) \u{00D7} 4"), Ok(()));
+        // Only evaluation notices these
+        assert_eq!(validate_expression("1 / 0"), Ok(()));
+        assert_eq!(validate_expression("sqrt(-1)"), Ok(()));
+
+        for bad in ["(2 + 3", "2 + 3)", "2 +", "* 4", "2 3", "", "2 $ 3"] {
+            assert!(validate_expression(bad).is_err(), "{:?} accepted", bad);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_negative_powers() {
+        assert_eq!(evaluate("-3^2"), Ok(-9.0));
+        assert_eq!(evaluate("(-3)^2"), Ok(9.0));
+        assert_eq!(evaluate("2^-1"), Ok(0.5));
+        assert_eq!(evaluate("10 - -3^2"), Ok(19.0));
+    }
+
+    #[test]
+    fn test_evaluate_display_symbols() {
+        assert_eq!(evaluate("6 \u{00D7} 7"), Ok(42.0));
+        assert_eq!(evaluate("9 \u{00F7} 3 \u{2212} 1"), Ok(2.0));
+        assert_eq!(evaluate("\u{221A}(16)"), Ok(4.0));
+        assert_eq!(evaluate("sqr(3) + isqrt(17)"), Ok(13.0));
+        assert_eq!(evaluate("max(3, 7) - min(3, 7)"), Ok(4.0));
+        assert_eq!(evaluate("round(47, 5)"), Ok(45.0));
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        assert_eq!(evaluate("1 / 0"), Err(CalcError::DivisionByZero));
+        assert_eq!(evaluate("2 +"), Err(CalcError::Syntax));
+        assert_eq!(evaluate("(2 + 3"), Err(CalcError::Syntax));
+        assert_eq!(evaluate("2 + 3)"), Err(CalcError::Syntax));
+        assert_eq!(evaluate("foo(2)"), Err(CalcError::Syntax));
+        assert_eq!(evaluate("2 $ 3"), Err(CalcError::Syntax));
+        assert_eq!(evaluate(""), Err(CalcError::Syntax));
+        assert_eq!(evaluate("sqrt(-1)"), Err(CalcError::InvalidInput));
+    }
+
+    #[test]
+    fn test_eval_rational() {
+        let exact = |input: &str| parse(input).and_then(|e| e.eval_rational());
+        assert_eq!(exact("1/3 + 1/3"), Rational::new(2, 3));
+        assert_eq!(exact("-(1/2) * 2/3"), Rational::new(-1, 3));
+        assert_eq!(exact("0.5 + 1"), Err(CalcError::InvalidInput));
+        assert_eq!(exact("sqrt(4)"), Err(CalcError::InvalidInput));
+    }
+
+    #[test]
+    fn test_evaluate_with_names() {
+        let resolve = |name: &str| if name == "x" { Some(5.0) } else { None };
+        assert_eq!(evaluate_with("x * 2", &resolve), Ok(10.0));
+        assert_eq!(evaluate_with("y", &resolve), Err(CalcError::Syntax));
+    }
+}
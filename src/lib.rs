@@ -1,74 +1,807 @@
 // Calculator library with core logic and tests
+mod eval;
+mod rational;
+
+pub use eval::{evaluate, evaluate_with, validate_expression};
+pub use rational::{apply_rational_op, Rational};
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// History entries kept unless `max_history` is configured
+pub const DEFAULT_MAX_HISTORY: usize = 10;
+pub const MEMORY_SLOTS: usize = 4;
+pub const DEFAULT_PRECISION: usize = 10;
+/// Seconds the 420 animation plays for unless configured otherwise
+pub const DEFAULT_BLAZE_DURATION: f32 = 20.0;
+
+/// Operator chars beyond the arithmetic `+ - * /`
+pub const OP_MIN: char = 'm';
+pub const OP_MAX: char = 'M';
+/// `a +% b` is `a` increased by `b` percent, `a -% b` decreased by it
+pub const OP_PERCENT_UP: char = 'u';
+pub const OP_PERCENT_DOWN: char = 'd';
+/// `a round b` rounds `a` to the nearest multiple of `b`
+pub const OP_ROUND_TO: char = 'r';
+
+const OPERATORS: [(char, &str); 10] = [
+    ('+', "+"),
+    ('-', "-"),
+    ('*', "\u{00D7}"),
+    ('/', "\u{00F7}"),
+    ('^', "^"),
+    (OP_MIN, "min"),
+    (OP_MAX, "max"),
+    (OP_PERCENT_UP, "+%"),
+    (OP_PERCENT_DOWN, "\u{2212}%"),
+    (OP_ROUND_TO, "round"),
+];
+
+/// Names `CalcApp::apply_unary` accepts, for a generic function keypad
+pub const UNARY_FUNCTIONS: [&str; 13] = [
+    "sqrt", "square", "reciprocal", "exp", "pow10", "sin", "cos", "tan", "ln", "log10",
+    "deg_to_rad", "rad_to_deg", "isqrt",
+];
+
+/// Every operator `apply_op` accepts, paired with its display symbol
+pub fn supported_operators() -> &'static [(char, &'static str)] {
+    &OPERATORS
+}
 
-const MAX_HISTORY: usize = 10;
+/// Displays longer than this many characters use the medium font
+pub const DISPLAY_MEDIUM_LEN: usize = 8;
+/// Displays longer than this many characters use the small font
+pub const DISPLAY_SMALL_LEN: usize = 12;
+pub const DISPLAY_FONT_LARGE: f32 = 46.0;
+pub const DISPLAY_FONT_MEDIUM: f32 = 32.0;
+pub const DISPLAY_FONT_SMALL: f32 = 24.0;
 
+/// Version of the public API and on-disk formats, "major.minor". A major
+/// bump means files written by older versions can't be read.
+pub const API_VERSION: &str = "1.2";
+
+/// First line of a history file, followed by the writer's `API_VERSION`
+const HISTORY_VERSION_PREFIX: &str = "#version ";
+
+/// Whether a history file written by `version` can be loaded: the major
+/// versions must match
+pub fn compatible_history_format(version: &str) -> bool {
+    fn major(v: &str) -> Option<u32> {
+        let (major, minor) = v.trim().split_once('.')?;
+        minor.parse::<u32>().ok()?;
+        major.parse().ok()
+    }
+    match (major(version), major(API_VERSION)) {
+        (Some(theirs), Some(ours)) => theirs == ours,
+        _ => false,
+    }
+}
+
+/// Environment variable that overrides where history is stored
+pub const HISTORY_PATH_ENV: &str = "CALCULATOR_HISTORY_PATH";
+
+/// History file location, in order of precedence:
+/// 1. `CALCULATOR_HISTORY_PATH`, when set and non-empty
+/// 2. `calc_history.txt` next to the executable
 pub fn history_path() -> PathBuf {
+    history_path_from(std::env::var_os(HISTORY_PATH_ENV))
+}
+
+/// `history_path` given the value of `CALCULATOR_HISTORY_PATH`
+pub fn history_path_from(env: Option<OsString>) -> PathBuf {
+    if let Some(path) = env.filter(|p| !p.is_empty()) {
+        return PathBuf::from(path);
+    }
     let mut path = std::env::current_exe().unwrap_or_default();
     path.set_file_name("calc_history.txt");
     path
 }
 
 pub fn load_history() -> Vec<HistoryEntry> {
-    let path = history_path();
-    let Ok(contents) = fs::read_to_string(&path) else {
+    load_history_from(&history_path())
+}
+
+/// Files from before the version header are read as compatible; a file
+/// with an incompatible version loads as empty history.
+pub fn load_history_from(path: &Path) -> Vec<HistoryEntry> {
+    let Ok(contents) = fs::read_to_string(path) else {
         return Vec::new();
     };
-    contents
-        .lines()
+    let mut lines = contents.lines().peekable();
+    if let Some(version) = lines.peek().and_then(|l| l.strip_prefix(HISTORY_VERSION_PREFIX)) {
+        if !compatible_history_format(version) {
+            return Vec::new();
+        }
+        lines.next();
+    }
+    lines
         .filter_map(|line| {
-            let (expr, result) = line.split_once('\t')?;
+            let (expr, rest) = line.split_once('\t')?;
+            let (result, kind) = match rest.split_once('\t') {
+                Some((result, tag)) => (result, EntryKind::from_tag(tag)),
+                None => (rest, EntryKind::Computation),
+            };
             Some(HistoryEntry {
-                expression: expr.to_string(),
-                result: result.to_string(),
+                expression: unescape_field(expr),
+                result: unescape_field(result),
+                kind,
             })
         })
         .collect()
 }
 
 pub fn save_history(history: &[HistoryEntry]) {
-    let path = history_path();
-    let content: String = history
+    save_history_to(&history_path(), history);
+}
+
+pub fn save_history_to(path: &Path, history: &[HistoryEntry]) {
+    let header = format!("{}{}", HISTORY_VERSION_PREFIX, API_VERSION);
+    let content: String = std::iter::once(header)
+        .chain(
+            history
+                .iter()
+                .map(|e| {
+                    format!(
+                        "{}\t{}{}",
+                        escape_field(&e.expression),
+                        escape_field(&e.result),
+                        e.kind.tag_field()
+                    )
+                }),
+        )
+        .collect::<Vec<_>>()
+        .join("\n");
+    // A custom location may point into a directory that doesn't exist yet
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let _ = fs::write(path, content);
+}
+
+/// Escape the history file's separators so a field can't split a record
+fn escape_field(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape_field(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+pub fn memory_path() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap_or_default();
+    path.set_file_name("calc_memory.txt");
+    path
+}
+
+pub fn load_memory() -> [f64; MEMORY_SLOTS] {
+    load_memory_from(&memory_path())
+}
+
+pub fn load_memory_from(path: &Path) -> [f64; MEMORY_SLOTS] {
+    let mut memory = [0.0; MEMORY_SLOTS];
+    if let Ok(contents) = fs::read_to_string(path) {
+        for (slot, line) in memory.iter_mut().zip(contents.lines()) {
+            *slot = line.trim().parse().unwrap_or(0.0);
+        }
+    }
+    memory
+}
+
+pub fn save_memory(memory: &[f64; MEMORY_SLOTS]) {
+    save_memory_to(&memory_path(), memory);
+}
+
+pub fn save_memory_to(path: &Path, memory: &[f64; MEMORY_SLOTS]) {
+    let content: String = memory
         .iter()
-        .map(|e| format!("{}\t{}", e.expression, e.result))
+        .map(|v| v.to_string())
         .collect::<Vec<_>>()
         .join("\n");
-    let _ = fs::write(&path, content);
+    let _ = fs::write(path, content);
+}
+
+pub fn settings_path() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap_or_default();
+    path.set_file_name("calc_settings.txt");
+    path
+}
+
+pub fn load_settings() -> Settings {
+    load_settings_from(&settings_path())
+}
+
+/// Read `key=value` lines; unknown keys and unparsable values keep their defaults
+pub fn load_settings_from(path: &Path) -> Settings {
+    let mut settings = Settings::default();
+    let Ok(contents) = fs::read_to_string(path) else {
+        return settings;
+    };
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match key {
+            "max_history" => settings.max_history = value.parse().unwrap_or(settings.max_history),
+            "precision" => settings.precision = value.parse().unwrap_or(settings.precision),
+            "easter_egg" => settings.easter_egg = value.parse().unwrap_or(settings.easter_egg),
+            "sound" => settings.sound = value.parse().unwrap_or(settings.sound),
+            "equals_suffix" => settings.equals_suffix = unescape_field(value),
+            "rounding" => settings.rounding = RoundingMode::from_name(value).unwrap_or(settings.rounding),
+            "fixed_decimals" => settings.fixed_decimals = value.parse().ok(),
+            "rational_mode" => settings.rational_mode = value.parse().unwrap_or(settings.rational_mode),
+            "autosave" => settings.autosave = value.parse().unwrap_or(settings.autosave),
+            "group_sep" => settings.group_sep = value.chars().next().unwrap_or(settings.group_sep),
+            "algebraic" => settings.algebraic = value.parse().unwrap_or(settings.algebraic),
+            "notation" => settings.notation = Notation::from_name(value).unwrap_or(settings.notation),
+            "layout" => settings.layout = Layout::from_name(value).unwrap_or(settings.layout),
+            "persist_history" => {
+                settings.persist_history = value.parse().unwrap_or(settings.persist_history)
+            }
+            "log_path" => {
+                settings.log_path = (!value.is_empty()).then(|| PathBuf::from(unescape_field(value)))
+            }
+            "blaze_duration" => {
+                if let Some(secs) = value.parse::<f32>().ok().filter(|s| s.is_finite() && *s > 0.0) {
+                    settings.blaze_duration = secs;
+                }
+            }
+            _ => {}
+        }
+    }
+    settings
+}
+
+pub fn save_settings(settings: &Settings) {
+    save_settings_to(&settings_path(), settings);
+}
+
+pub fn save_settings_to(path: &Path, settings: &Settings) {
+    let content = [
+        format!("max_history={}", settings.max_history),
+        format!("precision={}", settings.precision),
+        format!("easter_egg={}", settings.easter_egg),
+        format!("sound={}", settings.sound),
+        format!("equals_suffix={}", escape_field(&settings.equals_suffix)),
+        format!("rounding={}", settings.rounding.name()),
+        format!("fixed_decimals={}", settings.fixed_decimals.map(|d| d.to_string()).unwrap_or_default()),
+        format!("rational_mode={}", settings.rational_mode),
+        format!("persist_history={}", settings.persist_history),
+        format!("layout={}", settings.layout.name()),
+        format!("autosave={}", settings.autosave),
+        format!("group_sep={}", settings.group_sep),
+        format!("algebraic={}", settings.algebraic),
+        format!("notation={}", settings.notation.name()),
+        format!("blaze_duration={}", settings.blaze_duration),
+        format!(
+            "log_path={}",
+            settings
+                .log_path
+                .as_ref()
+                .map(|p| escape_field(&p.to_string_lossy()))
+                .unwrap_or_default()
+        ),
+    ]
+    .join("\n");
+    let _ = fs::write(path, content);
+}
+
+/// Append one timestamped line (`unix_seconds<TAB>expression<TAB>result`,
+/// plus `<TAB>assign` for a variable store) to the computation log.
+/// Best-effort: failures are ignored.
+pub fn append_log(path: &Path, entry: &HistoryEntry) {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let line = format!(
+        "{}\t{}\t{}{}\n",
+        timestamp,
+        escape_field(&entry.expression),
+        escape_field(&entry.result),
+        entry.kind.tag_field()
+    );
+    if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(path) {
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Number of history entries kept
+    pub max_history: usize,
+    /// Maximum decimals shown for non-integer results
+    pub precision: usize,
+    /// How results are rounded to `precision` decimals
+    pub rounding: RoundingMode,
+    /// Show exactly this many decimals, keeping trailing zeros ("5.00"),
+    /// in place of `precision`. Only applies to standard notation.
+    pub fixed_decimals: Option<usize>,
+    /// Keep `+ - * /` on integers and fractions exact (`1/3 + 1/3 = 2/3`)
+    pub rational_mode: bool,
+    /// Save history to disk and load it at startup; when off, history
+    /// only lives for the session
+    pub persist_history: bool,
+    /// Save history after every change; when off, call `flush_history`
+    pub autosave: bool,
+    /// Thousands separator shown in the display and stripped from pasted
+    /// numbers; with '.' the decimal point is written ','
+    pub group_sep: char,
+    /// Algebraic button mode: `2 + 3 × 4 =` gives 14 instead of chaining
+    /// left to right to 20
+    pub algebraic: bool,
+    /// How results are written; see `Notation`
+    pub notation: Notation,
+    /// Button layout the GUI shows
+    pub layout: Layout,
+    /// Enable the 420 animation
+    pub easter_egg: bool,
+    /// Play audio with the 420 animation
+    pub sound: bool,
+    /// Seconds the 420 animation plays for
+    pub blaze_duration: f32,
+    /// Appended to the expression line once a calculation completes
+    pub equals_suffix: String,
+    /// Append-only audit log of every computation, independent of history
+    pub log_path: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_history: DEFAULT_MAX_HISTORY,
+            precision: DEFAULT_PRECISION,
+            rounding: RoundingMode::default(),
+            fixed_decimals: None,
+            rational_mode: false,
+            persist_history: true,
+            autosave: true,
+            group_sep: ',',
+            algebraic: false,
+            notation: Notation::Standard,
+            layout: Layout::default(),
+            easter_egg: true,
+            sound: true,
+            blaze_duration: DEFAULT_BLAZE_DURATION,
+            equals_suffix: " =".to_string(),
+            log_path: None,
+        }
+    }
+}
+
+/// Rounding rule for the last kept decimal. `Down` and `Up` are toward and
+/// away from zero; `HalfUp` rounds ties away from zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RoundingMode {
+    HalfUp,
+    #[default]
+    HalfEven,
+    Down,
+    Up,
+}
+
+impl RoundingMode {
+    /// Name used in the settings file
+    pub fn name(self) -> &'static str {
+        match self {
+            RoundingMode::HalfUp => "half_up",
+            RoundingMode::HalfEven => "half_even",
+            RoundingMode::Down => "down",
+            RoundingMode::Up => "up",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "half_up" => Some(RoundingMode::HalfUp),
+            "half_even" => Some(RoundingMode::HalfEven),
+            "down" => Some(RoundingMode::Down),
+            "up" => Some(RoundingMode::Up),
+            _ => None,
+        }
+    }
+}
+
+/// Button layout. `Scientific` adds function keys and `Programmer` adds
+/// hex digits and a base toggle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Layout {
+    #[default]
+    Basic,
+    Scientific,
+    Programmer,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 3] = [Layout::Basic, Layout::Scientific, Layout::Programmer];
+
+    /// Name used in the settings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Basic => "basic",
+            Layout::Scientific => "scientific",
+            Layout::Programmer => "programmer",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "basic" => Some(Layout::Basic),
+            "scientific" => Some(Layout::Scientific),
+            "programmer" => Some(Layout::Programmer),
+            _ => None,
+        }
+    }
+
+    /// Label for the layout menu
+    pub fn label(self) -> &'static str {
+        match self {
+            Layout::Basic => "Basic",
+            Layout::Scientific => "Scientific",
+            Layout::Programmer => "Programmer",
+        }
+    }
+}
+
+/// How results are written. `Scientific` and `Engineering` treat the
+/// precision setting as a count of significant digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Notation {
+    /// Plain decimals rounded to `precision` places
+    #[default]
+    Standard,
+    /// One digit before the point: "1.2346e7"
+    Scientific,
+    /// Exponent a multiple of 3: "12.346e6"
+    Engineering,
+}
+
+impl Notation {
+    pub const ALL: [Notation; 3] = [Notation::Standard, Notation::Scientific, Notation::Engineering];
+
+    /// Name used in the settings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Notation::Standard => "standard",
+            Notation::Scientific => "scientific",
+            Notation::Engineering => "engineering",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(Notation::Standard),
+            "scientific" => Some(Notation::Scientific),
+            "engineering" => Some(Notation::Engineering),
+            _ => None,
+        }
+    }
+
+    /// Label for the view menu
+    pub fn label(self) -> &'static str {
+        match self {
+            Notation::Standard => "Standard notation",
+            Notation::Scientific => "Scientific notation",
+            Notation::Engineering => "Engineering notation",
+        }
+    }
+}
+
+/// Named constants that can be loaded into the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Constant {
+    Pi,
+    E,
+    /// Golden ratio φ
+    Phi,
+    Sqrt2,
+    /// Euler-Mascheroni constant γ
+    EulerGamma,
+}
+
+impl Constant {
+    pub const ALL: [Constant; 5] = [
+        Constant::Pi,
+        Constant::E,
+        Constant::Phi,
+        Constant::Sqrt2,
+        Constant::EulerGamma,
+    ];
+
+    pub fn value(self) -> f64 {
+        match self {
+            Constant::Pi => std::f64::consts::PI,
+            Constant::E => std::f64::consts::E,
+            Constant::Phi => 1.618_033_988_749_895,
+            Constant::Sqrt2 => std::f64::consts::SQRT_2,
+            Constant::EulerGamma => 0.577_215_664_901_532_9,
+        }
+    }
+
+    /// Look up a constant by the name used in typed expressions
+    /// ("pi", "e", "phi", "gamma")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pi" => Some(Constant::Pi),
+            "e" => Some(Constant::E),
+            "phi" => Some(Constant::Phi),
+            "gamma" => Some(Constant::EulerGamma),
+            _ => None,
+        }
+    }
+
+    /// Symbol shown in menus
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Constant::Pi => "\u{03C0}",
+            Constant::E => "e",
+            Constant::Phi => "\u{03C6}",
+            Constant::Sqrt2 => "\u{221A}2",
+            Constant::EulerGamma => "\u{03B3}",
+        }
+    }
 }
 
-#[derive(Clone)]
+/// Number base for programmer mode. In `Hex` the display holds an
+/// uppercase hex integer and results are truncated to integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Base {
+    #[default]
+    Decimal,
+    Hex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CalcError {
+    DivisionByZero,
+    InvalidInput,
+    Overflow,
+    UnknownOperator(char),
+    Syntax,
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcError::DivisionByZero => write!(f, "Cannot divide by zero"),
+            CalcError::InvalidInput => write!(f, "Invalid input"),
+            CalcError::Overflow => write!(f, "Overflow"),
+            CalcError::UnknownOperator(op) => write!(f, "Unknown operator '{}'", op),
+            CalcError::Syntax => write!(f, "Invalid expression"),
+        }
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+/// Apply a single binary operator. This is what `CalcApp::compute` uses.
+pub fn apply_op(a: f64, op: char, b: f64) -> Result<f64, CalcError> {
+    let result = match op {
+        '+' => a + b,
+        '-' => a - b,
+        '*' => a * b,
+        '/' => {
+            if b == 0.0 {
+                return Err(CalcError::DivisionByZero);
+            }
+            a / b
+        }
+        OP_MIN => a.min(b),
+        OP_MAX => a.max(b),
+        OP_PERCENT_UP => increase_by_percent(a, b),
+        OP_PERCENT_DOWN => decrease_by_percent(a, b),
+        OP_ROUND_TO => round_to_multiple(a, b).ok_or(CalcError::InvalidInput)?,
+        '^' => {
+            let result = a.powf(b);
+            if result.is_nan() {
+                return Err(CalcError::InvalidInput);
+            }
+            result
+        }
+        _ => return Err(CalcError::UnknownOperator(op)),
+    };
+    if result.is_finite() {
+        Ok(result)
+    } else {
+        Err(CalcError::Overflow)
+    }
+}
+
+/// What a history entry records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EntryKind {
+    #[default]
+    Computation,
+    /// A variable store; the expression is the variable name
+    Assignment,
+}
+
+impl EntryKind {
+    /// Third field of a history or log line; computations have none, so
+    /// files written before variables existed read back unchanged
+    fn tag_field(self) -> &'static str {
+        match self {
+            EntryKind::Computation => "",
+            EntryKind::Assignment => "\tassign",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Self {
+        if tag == "assign" {
+            EntryKind::Assignment
+        } else {
+            EntryKind::Computation
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HistoryEntry {
     pub expression: String,
     pub result: String,
+    #[serde(default)]
+    pub kind: EntryKind,
+}
+
+impl HistoryEntry {
+    pub fn new(expression: impl Into<String>, result: impl Into<String>) -> Self {
+        HistoryEntry { expression: expression.into(), result: result.into(), kind: EntryKind::Computation }
+    }
+
+    /// `name = value`, recorded when a variable is stored
+    pub fn assignment(name: impl Into<String>, value: impl Into<String>) -> Self {
+        HistoryEntry { expression: name.into(), result: value.into(), kind: EntryKind::Assignment }
+    }
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.expression, self.result)
+    }
 }
 
+/// Calculator state. Cloning copies the state only; persistence happens when
+/// a method saves, so a clone can be explored without touching the files.
+/// The whole state except the running animation can be saved as a session
+/// string (see `to_session_string`).
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CalcApp {
     pub display: String,
     pub expression: String,
     pub first_operand: Option<f64>,
+    /// The first operand as an exact fraction, for rational mode
+    pub first_operand_exact: Option<Rational>,
     pub operator: Option<char>,
+    /// Lower-precedence operations put off in algebraic mode, innermost
+    /// last: after `2 + 3 ×` this holds `(2, '+')`
+    #[serde(default)]
+    pub deferred: Vec<(f64, char)>,
     pub waiting_for_second: bool,
     pub just_computed: bool,
+    /// The display holds a value converted by the `%` key
+    pub last_was_percent: bool,
+    /// `as_percentage` turned the display into a percentage (0.25 -> 25)
+    #[serde(default)]
+    pub percentage_form: bool,
+    /// The display holds a second operand produced by a function key
+    /// (`9 + √`); typing replaces it rather than extending it
+    pub computed_operand: bool,
+    /// The memory value just recalled as the second operand, so that `%`
+    /// can take a percentage of it
+    #[serde(default)]
+    pub recalled_memory: Option<f64>,
+    /// Unrounded value of the last computed result
+    pub last_result: Option<f64>,
+    /// The displayed form of the last result isn't exact (`1/3`)
+    #[serde(default)]
+    pub was_rounded: bool,
+    /// Why the display shows "Error", while it does
+    #[serde(default)]
+    pub error: Option<CalcError>,
     pub history: Vec<HistoryEntry>,
     pub show_history: bool,
+    /// Memory registers M1-M4
+    pub memory: [f64; MEMORY_SLOTS],
+    /// Named values set with `store_variable`
+    #[serde(default)]
+    pub variables: BTreeMap<String, f64>,
+    pub settings: Settings,
+    /// Programmer-mode base the display is shown and typed in
+    #[serde(default)]
+    pub base: Base,
+    /// Operator and second operand of each calculation recorded for replay
+    #[serde(default)]
+    pub macro_steps: Vec<(char, f64)>,
+    /// Calculations are being appended to `macro_steps`
+    #[serde(default)]
+    pub recording_macro: bool,
+    /// INV was pressed: the next scientific function applies its inverse
+    #[serde(default)]
+    pub inverse_pending: bool,
+    /// K mode: `=` with nothing pending repeats the last operator and
+    /// second operand on the display
+    #[serde(default)]
+    pub constant_mode: bool,
+    /// Operator and second operand of the last completed calculation
+    #[serde(default)]
+    pub last_operator: Option<char>,
+    #[serde(default)]
+    pub last_operand: Option<f64>,
+    /// History as it was before the last `clear_history`, for undo
+    #[serde(skip)]
+    pub last_cleared_history: Option<Vec<HistoryEntry>>,
     /// Easter egg: triggers when result is 420
+    #[serde(skip)]
     pub blaze_it: bool,
+    #[serde(skip)]
     pub blaze_start: Option<std::time::Instant>,
+    #[serde(skip)]
     pub blaze_sound_played: bool,
 }
 
 impl CalcApp {
     pub fn new() -> Self {
+        let settings = load_settings();
+        let history = if settings.persist_history { load_history() } else { Vec::new() };
         Self {
             display: "0".to_string(),
             expression: String::new(),
             first_operand: None,
+            first_operand_exact: None,
             operator: None,
+            deferred: Vec::new(),
             waiting_for_second: false,
             just_computed: false,
-            history: load_history(),
+            last_was_percent: false,
+            percentage_form: false,
+            computed_operand: false,
+            recalled_memory: None,
+            last_result: None,
+            was_rounded: false,
+            error: None,
+            history,
             show_history: false,
+            memory: load_memory(),
+            variables: BTreeMap::new(),
+            settings,
+            base: Base::Decimal,
+            macro_steps: Vec::new(),
+            recording_macro: false,
+            inverse_pending: false,
+            constant_mode: false,
+            last_operator: None,
+            last_operand: None,
+            last_cleared_history: None,
             blaze_it: false,
             blaze_start: None,
             blaze_sound_played: false,
@@ -76,124 +809,583 @@ impl CalcApp {
     }
 
     pub fn add_history(&mut self, expression: String, result: String) {
-        self.history.push(HistoryEntry { expression, result });
-        if self.history.len() > MAX_HISTORY {
+        self.push_history(HistoryEntry::new(expression, result));
+    }
+
+    fn push_history(&mut self, entry: HistoryEntry) {
+        if let Some(log_path) = &self.settings.log_path {
+            append_log(log_path, &entry);
+        }
+        self.history.push(entry);
+        while self.history.len() > self.settings.max_history {
             self.history.remove(0);
         }
-        save_history(&self.history);
+        self.store_history();
+    }
+
+    /// Delete all history and save, keeping a copy that
+    /// `restore_cleared_history` can bring back
+    pub fn clear_history(&mut self) {
+        if !self.history.is_empty() {
+            self.last_cleared_history = Some(std::mem::take(&mut self.history));
+        }
+        self.store_history();
+    }
+
+    /// Undo the most recent `clear_history`. Entries added since then stay
+    /// after the restored ones. No-op if there's nothing to restore.
+    pub fn restore_cleared_history(&mut self) {
+        if let Some(mut restored) = self.last_cleared_history.take() {
+            restored.append(&mut self.history);
+            self.history = restored;
+            self.trim_history_to(self.settings.max_history);
+        }
+    }
+
+    /// Replace history with the rows of an `expression,result` CSV file
+    /// (RFC 4180, with or without that header) and save it in the native
+    /// format. An optional third `kind` column marks variable stores with
+    /// `assign`, as in the history file. Only the newest `max_history`
+    /// rows are kept. Returns how many malformed rows were skipped.
+    pub fn import_history_csv(&mut self, path: &Path) -> std::io::Result<usize> {
+        let text = fs::read_to_string(path)?;
+        let (records, mut skipped) = parse_csv(&text);
+        let mut records = records.into_iter().peekable();
+        let is_header = |r: &Vec<String>| {
+            (2..=3).contains(&r.len())
+                && r[0].eq_ignore_ascii_case("expression")
+                && r[1].eq_ignore_ascii_case("result")
+                && (r.len() == 2 || r[2].eq_ignore_ascii_case("kind"))
+        };
+        if records.peek().is_some_and(is_header) {
+            records.next();
+        }
+        self.history.clear();
+        for mut record in records {
+            let kind = match (record.len(), record.last().map(String::as_str)) {
+                (2, _) | (3, Some("")) => EntryKind::Computation,
+                (3, Some("assign")) => EntryKind::Assignment,
+                _ => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            record.truncate(2);
+            let result = record.pop().unwrap_or_default();
+            let expression = record.pop().unwrap_or_default();
+            self.history.push(HistoryEntry { expression, result, kind });
+        }
+        self.trim_history_to(self.settings.max_history);
+        Ok(skipped)
+    }
+
+    /// Write history to disk after a change, when `autosave` is on
+    fn store_history(&self) {
+        if self.settings.autosave {
+            self.flush_history();
+        }
+    }
+
+    /// Write history to disk now; needed with `autosave` off. Does nothing
+    /// when `persist_history` is off.
+    pub fn flush_history(&self) {
+        self.flush_history_to(&history_path());
+    }
+
+    pub fn flush_history_to(&self, path: &Path) {
+        if self.settings.persist_history {
+            save_history_to(path, &self.history);
+        }
+    }
+
+    /// Snapshot of the full session (display, pending operation, history,
+    /// memory and settings) as a JSON string
+    pub fn to_session_string(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Restore a session saved with `to_session_string`. The 420 animation is
+    /// not part of a session and starts off. Nothing is written to disk.
+    pub fn from_session_string(text: &str) -> Result<Self, CalcError> {
+        serde_json::from_str(text).map_err(|_| CalcError::InvalidInput)
+    }
+
+    /// Keep only the `keep` most recent history entries and save
+    pub fn trim_history_to(&mut self, keep: usize) {
+        let excess = self.history.len().saturating_sub(keep);
+        self.history.drain(..excess);
+        self.store_history();
+    }
+
+    /// Re-format every numeric history result the way `format` shows it
+    /// now, e.g. after changing precision or notation, and save.
+    /// Fractions, "Error" and other non-numeric results are left as-is, as
+    /// is all of history in hex mode, where "10" could be either base.
+    pub fn reformat_history(&mut self) {
+        if self.base == Base::Hex {
+            return;
+        }
+        let formatted: Vec<Option<String>> = self
+            .history
+            .iter()
+            .map(|entry| entry.result.parse::<f64>().ok().map(|value| self.format(value)))
+            .collect();
+        for (entry, result) in self.history.iter_mut().zip(formatted) {
+            if let Some(result) = result {
+                entry.result = result;
+            }
+        }
+        self.store_history();
     }
 
     pub fn input_digit(&mut self, d: char) {
+        if self.base == Base::Hex {
+            self.input_hex_digit(d);
+            return;
+        }
         if self.just_computed {
             self.clear_state();
             self.just_computed = false;
         }
+        self.start_over_entry();
         if self.waiting_for_second {
             self.display = d.to_string();
             self.waiting_for_second = false;
         } else if self.display == "0" {
             self.display = d.to_string();
+        } else if self.display == "-0" {
+            self.display = format!("-{}", d);
         } else {
             self.display.push(d);
         }
+        // A long exponent like 1e400 overflows f64
+        if self.display.parse::<f64>().is_ok_and(|v| !v.is_finite()) {
+            self.set_error(CalcError::Overflow);
+        }
+    }
+
+    /// Programmer-mode digit key: `0`-`9` and `A`-`F` (either case) while
+    /// in `Base::Hex`. Other characters, other bases, and digits that would
+    /// overflow a 64-bit value are ignored.
+    pub fn input_hex_digit(&mut self, c: char) {
+        if self.base != Base::Hex || !c.is_ascii_hexdigit() {
+            return;
+        }
+        let c = c.to_ascii_uppercase();
+        if self.just_computed || self.is_error() {
+            self.clear_state();
+        }
+        self.start_over_entry();
+        let candidate = if self.waiting_for_second || self.display == "0" {
+            c.to_string()
+        } else if self.display == "-0" {
+            format!("-{}", c)
+        } else {
+            format!("{}{}", self.display, c)
+        };
+        if i64::from_str_radix(&candidate, 16).is_ok() {
+            self.display = candidate;
+            self.waiting_for_second = false;
+        }
+    }
+
+    /// Choose the button layout and save it. Leaving the programmer layout
+    /// switches the display back to decimal.
+    pub fn set_layout(&mut self, layout: Layout) {
+        if layout != Layout::Programmer {
+            self.set_base(Base::Decimal);
+        }
+        self.settings.layout = layout;
+        save_settings(&self.settings);
+    }
+
+    /// Choose how results are written and save it. A result on the display
+    /// is rewritten in the new notation.
+    pub fn set_notation(&mut self, notation: Notation) {
+        self.settings.notation = notation;
+        save_settings(&self.settings);
+        if let (true, Some(r)) = (self.is_result(), self.last_result) {
+            self.display = self.format(r);
+        }
+    }
+
+    /// Switch programmer-mode base, converting the displayed value.
+    /// Fractions are truncated when entering hex.
+    pub fn set_base(&mut self, base: Base) {
+        if base == self.base {
+            return;
+        }
+        let value = self.current_value();
+        self.base = base;
+        if let Some(v) = value {
+            self.display = self.format(v);
+        }
+    }
+
+    /// Desk-calculator "00" key: two zeros under the same rules as `input_digit`
+    pub fn input_double_zero(&mut self) {
+        self.input_digit('0');
+        self.input_digit('0');
     }
 
     pub fn input_dot(&mut self) {
+        if self.base == Base::Hex {
+            return;
+        }
         if self.just_computed {
             self.clear_state();
             self.just_computed = false;
         }
+        self.start_over_entry();
         if self.waiting_for_second {
             self.display = "0.".to_string();
             self.waiting_for_second = false;
-        } else if !self.display.contains('.') {
+        } else if !self.display.contains('.') && !self.display.contains('e') {
             self.display.push('.');
         }
     }
 
+    /// EXP key: start typing a power-of-ten exponent (`1e5`)
+    pub fn input_exponent(&mut self) {
+        if self.base == Base::Hex
+            || self.is_error()
+            || self.just_computed
+            || self.waiting_for_second
+            || self.last_was_percent
+            || self.display.contains('e')
+        {
+            return;
+        }
+        if self.display.ends_with('.') {
+            self.display.pop();
+        }
+        self.display.push('e');
+    }
+
+    /// Display symbol from `supported_operators`, "?" for unknown chars
     pub fn op_symbol(op: char) -> &'static str {
+        OPERATORS
+            .iter()
+            .find(|&&(c, _)| c == op)
+            .map_or("?", |&(_, symbol)| symbol)
+    }
+
+    /// Display symbol of the pending operator, `None` when nothing is pending
+    pub fn current_op_symbol(&self) -> Option<&'static str> {
+        self.operator.map(Self::op_symbol)
+    }
+
+    /// Expression line while waiting for a second operand: "2 + 3 ×" with
+    /// any deferred operations in front of `shown op`
+    fn pending_expression(&self, shown: &str, op: char) -> String {
+        format!("{}{} {}", self.deferred_prefix(), shown, Self::op_symbol(op))
+    }
+
+    /// "2 + " for each operation waiting in `deferred`
+    fn deferred_prefix(&self) -> String {
+        self.deferred
+            .iter()
+            .map(|&(v, op)| format!("{} {} ", self.format(v), Self::op_symbol(op)))
+            .collect()
+    }
+
+    /// Expression text for a binary operation: "3 + 7", or "max(3, 7)" for
+    /// operators written as functions. A negative second operand is
+    /// parenthesized after an infix operator: "5 + (-3)". `b` may already
+    /// be wrapped by `format_operand_for_expr`.
+    pub fn binary_expression(a: &str, op: char, b: &str) -> String {
         match op {
-            '+' => "+",
-            '-' => "-",
-            '*' => "\u{00D7}",
-            '/' => "\u{00F7}",
-            _ => "?",
+            OP_MIN | OP_MAX | OP_ROUND_TO => {
+                // Arguments need no parentheses: "max(5, -3)"
+                let unwrapped = b.strip_prefix("(-").and_then(|b| b.strip_suffix(')')).map(|b| format!("-{}", b));
+                format!("{}({}, {})", Self::op_symbol(op), a, unwrapped.as_deref().unwrap_or(b))
+            }
+            _ => format!("{} {} {}", a, Self::op_symbol(op), wrap_negative(b)),
         }
     }
 
+    /// A number as it appears after an operator in the expression line,
+    /// with negatives in parentheses
+    pub fn format_operand_for_expr(&self, n: f64) -> String {
+        wrap_negative(&self.format(n))
+    }
+
     pub fn input_operator(&mut self, op: char) {
-        if self.display == "Error" {
+        if self.is_error() {
+            return;
+        }
+        // An operator straight after another one only replaces it
+        if let (true, Some(mut a)) = (self.waiting_for_second, self.first_operand) {
+            // `2 + 3 ×` corrected to `2 + 3 -` no longer defers the addition
+            while let Some(&(s, q)) = self.deferred.last() {
+                if !binds_before(q, op) {
+                    break;
+                }
+                match apply_op(s, q, a) {
+                    Ok(r) => a = r,
+                    Err(e) => {
+                        self.set_error(e);
+                        return;
+                    }
+                }
+                self.deferred.pop();
+                self.first_operand = Some(a);
+                self.first_operand_exact = None;
+                self.display = self.format(a);
+            }
+            self.operator = Some(op);
+            self.expression = self.pending_expression(&self.format(a), op);
+            return;
+        }
+        // With nothing to subtract from, a leading minus starts a negative number
+        if op == '-'
+            && self.first_operand.is_none()
+            && !self.just_computed
+            && (self.display == "0" || self.display == "-0")
+        {
+            self.display = if self.display == "0" { "-0" } else { "0" }.to_string();
             return;
         }
-        if let Ok(val) = self.display.parse::<f64>() {
-            if self.first_operand.is_some() && !self.waiting_for_second {
-                self.compute();
-                if self.display == "Error" {
+        if let Some(val) = self.current_value() {
+            if let (Some(a), Some(pending)) = (self.first_operand, self.operator) {
+                // Powers group right to left in both modes, as in `evaluate`
+                let defer = if self.settings.algebraic {
+                    !binds_before(pending, op)
+                } else {
+                    pending == '^' && op == '^'
+                };
+                if defer {
+                    // `2 + 3 ×`: put the addition off until the product is known
+                    self.deferred.push((a, pending));
+                } else if self.settings.algebraic {
+                    // `2 + 3 × 4 ÷`: finish the product, and any deferred
+                    // operation binding as tightly, but keep the addition
+                    let mut deferred = std::mem::take(&mut self.deferred);
+                    let keep = deferred.iter().rposition(|&(_, q)| !binds_before(q, op)).map_or(0, |i| i + 1);
+                    self.deferred = deferred.split_off(keep);
+                    // A partial result under a kept addition isn't a macro
+                    // step; the addition records it when it completes
+                    let recording = self.recording_macro;
+                    self.recording_macro &= deferred.is_empty();
+                    self.compute();
+                    self.recording_macro = recording;
+                    self.deferred = deferred;
+                } else {
+                    self.compute();
+                }
+                if self.is_error() {
                     return;
                 }
             }
-            let current: f64 = self.display.parse().unwrap_or(val);
-            self.expression = format!("{} {}", format_number(current), Self::op_symbol(op));
+            let current = self.current_value().unwrap_or(val);
+            self.first_operand_exact = Rational::parse(&self.display);
+            let shown = match self.first_operand_exact {
+                Some(exact) if self.rational_active() => self.exact_text(exact),
+                _ => self.format(current),
+            };
+            self.expression = self.pending_expression(&shown, op);
             self.first_operand = Some(current);
             self.operator = Some(op);
             self.waiting_for_second = true;
             self.just_computed = false;
+            self.last_was_percent = false;
         }
     }
 
-    pub fn compute(&mut self) {
-        if let (Some(a), Some(op)) = (self.first_operand, self.operator) {
-            if let Ok(b) = self.display.parse::<f64>() {
-                let expr = format!(
-                    "{} {} {}",
-                    format_number(a),
-                    Self::op_symbol(op),
-                    format_number(b)
-                );
-                self.expression = format!("{} =", expr);
-                let result = match op {
-                    '+' => Some(a + b),
-                    '-' => Some(a - b),
-                    '*' => Some(a * b),
-                    '/' => {
-                        if b == 0.0 {
-                            None
-                        } else {
-                            Some(a / b)
-                        }
-                    }
-                    _ => Some(0.0),
-                };
-                match result {
-                    Some(r) => {
-                        let result_str = format_number(r);
-                        self.add_history(expr, result_str.clone());
-                        self.display = result_str;
-                        if r == 420.0 {
-                            self.blaze_it = true;
-                            self.blaze_start = Some(std::time::Instant::now());
-                        }
+    /// Exchange the first operand with the typed second one, so `10 - 3`
+    /// becomes `3 - 10`. Does nothing until a second operand has been
+    /// entered (after just `10 -` there is nothing to swap with).
+    pub fn swap_operands(&mut self) {
+        if self.waiting_for_second || self.just_computed {
+            return;
+        }
+        let (Some(a), Some(op)) = (self.first_operand, self.operator) else {
+            return;
+        };
+        let Some(b) = self.current_value() else {
+            return;
+        };
+        let b_exact = Rational::parse(&self.display);
+        self.display = match self.first_operand_exact {
+            Some(exact) if self.rational_active() => self.exact_text(exact),
+            _ => self.format(a),
+        };
+        self.first_operand = Some(b);
+        self.first_operand_exact = b_exact;
+        self.expression = self.pending_expression(&self.format(b), op);
+        self.last_was_percent = false;
+        self.computed_operand = true;
+    }
+
+    /// The `=` key: finish the pending operation and show the result. With
+    /// no pending operation (a fresh "0", or a typed number on its own) it
+    /// is a no-op and nothing is added to history.
+    ///
+    /// If no second operand was typed (`5 + =`), the first operand is
+    /// repeated as the second, like most pocket calculators: `5 + 5 = 10`.
+    ///
+    /// In K mode (`constant_mode`) `=` with nothing pending applies the last
+    /// operator and second operand again: `2 + 5 = = =` shows 7, 12, 17.
+    pub fn compute(&mut self) {
+        if let (true, None, Some(op), Some(b)) =
+            (self.constant_mode, self.operator, self.last_operator, self.last_operand)
+        {
+            if let Some(a) = self.current_value() {
+                self.first_operand = Some(a);
+                self.first_operand_exact = Rational::parse(&self.display);
+                self.operator = Some(op);
+                self.display = self.format(b);
+            }
+        }
+        if let (Some(a), Some(op)) = (self.first_operand, self.operator) {
+            if let Some(b) = self.current_value() {
+                if self.recording_macro {
+                    self.record_step(a, op, b);
+                }
+                self.last_operator = Some(op);
+                self.last_operand = Some(b);
+                let exact = match (self.first_operand_exact, Rational::parse(&self.display)) {
+                    (Some(x), Some(y)) if self.rational_active() && self.deferred.is_empty() => {
+                        apply_rational_op(x, op, y).ok().map(|r| (x, y, r))
                     }
-                    None => {
-                        self.display = "Error".to_string();
-                        self.expression = "Cannot divide by zero".to_string();
+                    _ => None,
+                };
+                if let Some((x, y, r)) = exact {
+                    let expr = Self::binary_expression(&self.exact_text(x), op, &self.exact_text(y));
+                    self.expression = format!("{}{}", expr, self.settings.equals_suffix);
+                    self.show_exact_result(expr, r);
+                } else {
+                    let expr = format!(
+                        "{}{}",
+                        self.deferred_prefix(),
+                        Self::binary_expression(&self.format(a), op, &self.format_operand_for_expr(b))
+                    );
+                    self.expression = format!("{}{}", expr, self.settings.equals_suffix);
+                    match self.apply_pending(a, op, b) {
+                        Ok(r) => self.show_result(expr, r),
+                        Err(e) => self.set_error(e),
                     }
                 }
+                self.deferred.clear();
                 self.first_operand = None;
+                self.first_operand_exact = None;
                 self.operator = None;
                 self.waiting_for_second = false;
                 self.just_computed = true;
+                self.last_was_percent = false;
+            }
+        }
+    }
+
+    /// Add the calculation `=` is finishing to the macro. With operations
+    /// deferred, the step is the outermost one applied to everything after
+    /// it: `7 + 5 × 2` records `+ 10`.
+    fn record_step(&mut self, a: f64, op: char, b: f64) {
+        let Some((&(_, outer), inner)) = self.deferred.split_first() else {
+            self.macro_steps.push((op, b));
+            return;
+        };
+        let rest = apply_op(a, op, b).and_then(|r| inner.iter().rev().try_fold(r, |r, &(s, q)| apply_op(s, q, r)));
+        if let Ok(rest) = rest {
+            self.macro_steps.push((outer, rest));
+        }
+    }
+
+    /// `a op b` folded into any deferred operations, innermost first:
+    /// `2 + 3 × 4` is 3 × 4, then 2 + 12
+    fn apply_pending(&self, a: f64, op: char, b: f64) -> Result<f64, CalcError> {
+        let r = apply_op(a, op, b)?;
+        self.deferred.iter().rev().try_fold(r, |r, &(s, q)| apply_op(s, q, r))
+    }
+
+    /// What `=` would show now, without changing any state or history.
+    /// With nothing pending that's the displayed value, or in K mode the
+    /// last operation repeated on it; while the display shows an error,
+    /// that error.
+    pub fn preview_compute(&self) -> Result<f64, CalcError> {
+        let Some(b) = self.current_value() else {
+            return Err(self.error.unwrap_or(CalcError::InvalidInput));
+        };
+        match (self.first_operand, self.operator) {
+            (Some(a), Some(op)) => self.apply_pending(a, op, b),
+            _ => match (self.constant_mode, self.last_operator, self.last_operand) {
+                (true, Some(op), Some(k)) => self.apply_pending(b, op, k),
+                _ => Ok(b),
+            },
+        }
+    }
+
+    /// Turn K (constant) mode on or off
+    pub fn toggle_constant_mode(&mut self) {
+        self.constant_mode = !self.constant_mode;
+    }
+
+    /// Start recording a macro, discarding the previous one. Every
+    /// two-operand calculation completed while recording (`+ 5`, `× 2`) is
+    /// captured as a step; function keys are not.
+    pub fn record_macro(&mut self) {
+        self.macro_steps.clear();
+        self.recording_macro = true;
+    }
+
+    pub fn stop_macro(&mut self) {
+        self.recording_macro = false;
+    }
+
+    /// Apply the recorded steps in order to the displayed value, recording
+    /// the whole chain as one history entry: "(10 + 5) × 2 = 30"
+    pub fn replay_macro(&mut self) {
+        if self.macro_steps.is_empty() || self.recording_macro {
+            return;
+        }
+        let Some(start) = self.current_value() else {
+            return;
+        };
+        let mut value = start;
+        let mut expr = self.format(start);
+        for (i, &(op, b)) in self.macro_steps.iter().enumerate() {
+            let lhs = if i == 0 { expr } else { format!("({})", expr) };
+            expr = Self::binary_expression(&lhs, op, &self.format_operand_for_expr(b));
+            match apply_op(value, op, b) {
+                Ok(r) => value = r,
+                Err(e) => {
+                    self.set_error(e);
+                    return;
+                }
             }
         }
+        self.first_operand = None;
+        self.first_operand_exact = None;
+        self.operator = None;
+        self.deferred.clear();
+        self.waiting_for_second = false;
+        self.expression = format!("{}{}", expr, self.settings.equals_suffix);
+        self.show_result(expr, value);
     }
 
     pub fn clear_state(&mut self) {
         self.display = "0".to_string();
         self.expression.clear();
         self.first_operand = None;
+        self.first_operand_exact = None;
         self.operator = None;
+        self.deferred.clear();
         self.waiting_for_second = false;
         self.just_computed = false;
+        self.last_was_percent = false;
+        self.percentage_form = false;
+        self.computed_operand = false;
+        self.recalled_memory = None;
+        self.last_result = None;
+        self.was_rounded = false;
+        self.error = None;
+        self.inverse_pending = false;
+        self.cancel_blaze();
+    }
+
+    /// Stop the 420 easter egg animation early. The GUI stops the melody
+    /// as soon as `blaze_it` is off.
+    pub fn cancel_blaze(&mut self) {
         self.blaze_it = false;
         self.blaze_start = None;
         self.blaze_sound_played = false;
@@ -203,258 +1395,3845 @@ impl CalcApp {
         self.clear_state();
     }
 
-    pub fn clear_entry(&mut self) {
-        self.display = "0".to_string();
+    pub fn clear_entry(&mut self) {
+        self.display = "0".to_string();
+        self.recalled_memory = None;
+        self.last_was_percent = false;
+        self.just_computed = false;
+        self.error = None;
+    }
+
+    /// Blank the expression line only, leaving the display and any pending
+    /// operation untouched
+    pub fn clear_expression(&mut self) {
+        self.expression.clear();
+    }
+
+    /// Delete the last typed character. Right after an operator (`5 +`) it
+    /// cancels the pending operation instead, so the first operand can be
+    /// edited again.
+    pub fn backspace(&mut self) {
+        if self.is_error() || self.just_computed {
+            return;
+        }
+        if self.waiting_for_second {
+            self.first_operand = None;
+            self.operator = None;
+            self.waiting_for_second = false;
+            self.expression.clear();
+            // `2 + 3 ×` backs up to `2 +` with 3 as the typed operand
+            if let Some((a, op)) = self.deferred.pop() {
+                self.first_operand = Some(a);
+                self.operator = Some(op);
+                self.expression = self.pending_expression(&self.format(a), op);
+            }
+            return;
+        }
+        self.last_was_percent = false;
+        if self.display.len() > 1 {
+            self.display.pop();
+            if self.display == "-" {
+                self.display = "0".to_string();
+            }
+        } else {
+            self.display = "0".to_string();
+        }
+    }
+
+    pub fn toggle_sign(&mut self) {
+        if self.is_error() || self.display == "0" {
+            return;
+        }
+        // While typing scientific notation, +/- applies to the exponent,
+        // even before its digits ("1e" -> "1e-"). A finished value such as
+        // a result in scientific notation is negated as a whole.
+        let typing = !self.just_computed
+            && !self.waiting_for_second
+            && !self.computed_operand
+            && !self.last_was_percent;
+        if let Some((mantissa, exponent)) = self.display.split_once('e').filter(|_| typing) {
+            let exponent = exponent.strip_prefix('-').unwrap_or(exponent);
+            if mantissa.parse::<f64>().is_err() || !exponent.chars().all(|c| c.is_ascii_digit()) {
+                return;
+            }
+            let pos = mantissa.len() + 1;
+            if self.display[pos..].starts_with('-') {
+                self.display.remove(pos);
+            } else {
+                self.display.insert(pos, '-');
+            }
+            return;
+        }
+        // Anything else that isn't a number (e.g. a restored "Error") is left alone
+        if self.current_value().is_none() {
+            return;
+        }
+        if self.display.starts_with('-') {
+            self.display.remove(0);
+        } else {
+            self.display.insert(0, '-');
+        }
+    }
+
+    /// Flip the sign of a computed result so the next operator continues
+    /// from its negative. Unlike `toggle_sign` the whole value is negated
+    /// even in scientific notation ("1.2e7" -> "-1.2e7", not "1.2e-7"), and
+    /// `ans` follows when the display is the last result rather than a
+    /// recalled value. Before a result it acts like `toggle_sign`.
+    pub fn negate_result(&mut self) {
+        if !self.is_result() {
+            self.toggle_sign();
+            return;
+        }
+        if self.current_value() == Some(0.0) {
+            return;
+        }
+        // A recalled value (MR, a constant) is shown like a result but
+        // isn't `ans`; only follow along when the display is the result
+        let shows_last = self.last_result.is_some_and(|r| self.format(r) == self.display);
+        match self.display.strip_prefix('-') {
+            Some(rest) => self.display = rest.to_string(),
+            None => self.display.insert(0, '-'),
+        }
+        if shows_last {
+            self.last_result = self.last_result.map(|r| -r);
+        }
+    }
+
+    /// Percent key. On its own, `x%` is `x / 100`. With a pending `+` or `-`
+    /// it is `x` percent of the first operand (`200 + 10% = 220`), and with a
+    /// pending `*` or `/` it is `x / 100` (`50 * 10% = 5`), so chains like
+    /// `50% + 10% =` give 0.55. The converted value is marked with
+    /// `last_was_percent`: pressing `%` again is a no-op rather than dividing
+    /// twice, and typing a digit replaces the value instead of extending it.
+    ///
+    /// Since chaining computes the running total first, each percentage in
+    /// `100 + 10% + 5%` applies to the total so far: 110, then 115.5.
+    ///
+    /// Without a pending operation `%` finishes a calculation of its own:
+    /// `200 = %` shows 2 and records "200% = 2" in history. After `MR` as
+    /// the second operand (`15 × MR %`) it completes "15% of memory".
+    pub fn percent(&mut self) {
+        if self.last_was_percent {
+            return;
+        }
+        if let (Some(whole), Some(pct), Some(_), false) = (
+            self.recalled_memory,
+            self.first_operand,
+            self.operator,
+            self.waiting_for_second,
+        ) {
+            let expr = format!("{}% of {}", self.format(pct), self.format(whole));
+            self.expression = format!("{}{}", expr, self.settings.equals_suffix);
+            self.first_operand = None;
+            self.first_operand_exact = None;
+            self.operator = None;
+            self.deferred.clear();
+            self.recalled_memory = None;
+            self.show_result(expr, pct * whole / 100.0);
+            return;
+        }
+        if let Some(val) = self.current_value() {
+            let value = match (self.first_operand, self.operator) {
+                (Some(base), Some('+' | '-')) => base * val / 100.0,
+                _ => val / 100.0,
+            };
+            if self.operator.is_none() {
+                let expr = format!("{}%", self.display);
+                self.expression = format!("{}{}", expr, self.settings.equals_suffix);
+                self.show_result(expr, value);
+            } else {
+                self.display = self.format(value);
+                self.waiting_for_second = false;
+            }
+            self.last_was_percent = true;
+        }
+    }
+
+    fn start_over_entry(&mut self) {
+        self.recalled_memory = None;
+        self.percentage_form = false;
+        if self.last_was_percent || self.computed_operand {
+            self.display = "0".to_string();
+            self.last_was_percent = false;
+            self.computed_operand = false;
+        }
+    }
+
+    /// Multiply the display by 100 to show it as a percentage, 0.25 -> 25,
+    /// marked with `percentage_form`. Unlike `%` nothing is recorded, but
+    /// the display now holds 25: the next operator or `=` uses that value.
+    pub fn as_percentage(&mut self) {
+        if let Some(value) = self.current_value() {
+            self.load_value(value * 100.0);
+            self.percentage_form = true;
+        }
+    }
+
+    /// Rewrite the display as a decimal fraction, 25 -> 0.25
+    pub fn as_decimal(&mut self) {
+        if let Some(value) = self.current_value() {
+            self.load_value(value / 100.0);
+        }
+    }
+
+    /// Add the displayed value to a memory register (M+)
+    pub fn memory_add(&mut self, slot: usize) {
+        let Some(val) = self.current_value() else {
+            return;
+        };
+        if let Some(register) = self.memory.get_mut(slot) {
+            *register += val;
+            save_memory(&self.memory);
+        }
+    }
+
+    /// Exchange the displayed value with a memory register: the display is
+    /// stored and the old register value is shown. Does nothing while the
+    /// display shows an error, so nothing invalid is stored.
+    pub fn memory_swap(&mut self, slot: usize) {
+        let Some(val) = self.current_value() else {
+            return;
+        };
+        let Some(register) = self.memory.get_mut(slot) else {
+            return;
+        };
+        let previous = std::mem::replace(register, val);
+        save_memory(&self.memory);
+        self.load_value(previous);
+    }
+
+    /// Load a memory register into the display (MR)
+    pub fn memory_recall(&mut self, slot: usize) {
+        let Some(&value) = self.memory.get(slot) else {
+            return;
+        };
+        self.load_value(value);
+        if self.first_operand.is_some() {
+            self.recalled_memory = Some(value);
+        }
+    }
+
+    /// Load a named constant into the display, like recalling memory
+    pub fn insert_constant(&mut self, c: Constant) {
+        self.load_value(c.value());
+    }
+
+    /// Put a recalled value in the display as the current operand
+    fn load_value(&mut self, value: f64) {
+        if self.is_error() {
+            self.clear_state();
+        }
+        self.display = self.format(value);
+        self.last_was_percent = false;
+        self.percentage_form = false;
+        self.was_rounded = false;
+        self.recalled_memory = None;
+        if self.first_operand.is_some() {
+            // Recalled value becomes the second operand, replaced by typing
+            self.waiting_for_second = false;
+            self.computed_operand = true;
+        } else {
+            // Like a result: usable by an operator, replaced by typing
+            self.just_computed = true;
+        }
+    }
+
+    /// Reset a memory register to zero (MC)
+    pub fn memory_clear(&mut self, slot: usize) {
+        if let Some(register) = self.memory.get_mut(slot) {
+            *register = 0.0;
+            save_memory(&self.memory);
+        }
+    }
+
+    pub fn memory_values(&self) -> [f64; MEMORY_SLOTS] {
+        self.memory
+    }
+
+    /// Store the displayed value in a variable and record `name = value`
+    /// in history as an assignment. Names are case-insensitive, like the
+    /// names `evaluate` reads, and kept in lowercase. Fails for an invalid
+    /// name (see `is_variable_name`) or while the display shows an error.
+    pub fn store_variable(&mut self, name: &str) -> Result<(), CalcError> {
+        if !is_variable_name(name) {
+            return Err(CalcError::InvalidInput);
+        }
+        let value = self.current_value().ok_or(CalcError::InvalidInput)?;
+        let name = name.to_ascii_lowercase();
+        self.push_history(HistoryEntry::assignment(name.as_str(), self.format(value)));
+        self.variables.insert(name, value);
+        Ok(())
+    }
+
+    /// Load the result of `history[index]` (oldest first) into the display,
+    /// like recalling memory; the result is read in the current base.
+    /// Returns false, changing nothing, when the index is out of range or
+    /// the result isn't a number ("Error").
+    pub fn use_history_result(&mut self, index: usize) -> bool {
+        let Some(value) = self
+            .history
+            .get(index)
+            .and_then(|entry| self.parse_value(&entry.result))
+            .filter(|v| v.is_finite())
+        else {
+            return false;
+        };
+        self.load_value(value);
+        true
+    }
+
+    /// Load a variable into the display, like recalling memory. Unknown
+    /// names are ignored.
+    pub fn recall_variable(&mut self, name: &str) {
+        if let Some(&value) = self.variables.get(&name.to_ascii_lowercase()) {
+            self.load_value(value);
+        }
+    }
+
+    /// Apply a one-argument function by name (see `UNARY_FUNCTIONS`), as
+    /// if its key was pressed. Names match the methods, except `sqrt` for
+    /// `square_root` and `isqrt` for `integer_sqrt`. Unknown names are
+    /// `InvalidInput` and leave the state alone; errors from the function
+    /// itself show on the display as usual.
+    pub fn apply_unary(&mut self, name: &str) -> Result<(), CalcError> {
+        match name {
+            "sqrt" => self.square_root(),
+            "square" => self.square(),
+            "reciprocal" => self.reciprocal(),
+            "exp" => self.exp(),
+            "pow10" => self.pow10(),
+            "sin" => self.sin(),
+            "cos" => self.cos(),
+            "tan" => self.tan(),
+            "ln" => self.ln(),
+            "log10" => self.log10(),
+            "deg_to_rad" => self.deg_to_rad(),
+            "rad_to_deg" => self.rad_to_deg(),
+            "isqrt" => self.integer_sqrt(),
+            _ => return Err(CalcError::InvalidInput),
+        }
+        Ok(())
+    }
+
+    /// 1/x
+    pub fn reciprocal(&mut self) {
+        self.apply_unary_with(
+            |x| format!("1/({})", x),
+            |val| {
+                if val == 0.0 {
+                    Err(CalcError::DivisionByZero)
+                } else {
+                    Ok(1.0 / val)
+                }
+            },
+        );
+    }
+
+    /// x²
+    pub fn square(&mut self) {
+        self.apply_unary_with(|x| format!("sqr({})", x), |val| Ok(val * val));
+    }
+
+    /// √x
+    pub fn square_root(&mut self) {
+        self.apply_unary_with(
+            |x| format!("\u{221A}({})", x),
+            |val| {
+                if val < 0.0 {
+                    Err(CalcError::InvalidInput)
+                } else {
+                    Ok(val.sqrt())
+                }
+            },
+        );
+    }
+
+    /// e^x
+    pub fn exp(&mut self) {
+        self.apply_unary_with(|x| format!("e^{}", x), |val| Ok(val.exp()));
+    }
+
+    /// 10^x
+    pub fn pow10(&mut self) {
+        self.apply_unary_with(|x| format!("10^{}", x), |val| Ok(10f64.powf(val)));
+    }
+
+    /// The INV key: flip whether the next scientific function (sin, cos,
+    /// tan, ln, log) applies its inverse
+    pub fn toggle_inverse(&mut self) {
+        self.inverse_pending = !self.inverse_pending;
+    }
+
+    /// Sine of the displayed value in radians; asin after INV
+    pub fn sin(&mut self) {
+        if std::mem::take(&mut self.inverse_pending) {
+            self.apply_unary_with(|x| format!("asin({})", x), checked_arc(f64::asin));
+        } else {
+            self.apply_unary_with(|x| format!("sin({})", x), |val| Ok(val.sin()));
+        }
+    }
+
+    /// Cosine in radians; acos after INV
+    pub fn cos(&mut self) {
+        if std::mem::take(&mut self.inverse_pending) {
+            self.apply_unary_with(|x| format!("acos({})", x), checked_arc(f64::acos));
+        } else {
+            self.apply_unary_with(|x| format!("cos({})", x), |val| Ok(val.cos()));
+        }
+    }
+
+    /// Tangent in radians; atan after INV
+    pub fn tan(&mut self) {
+        if std::mem::take(&mut self.inverse_pending) {
+            self.apply_unary_with(|x| format!("atan({})", x), |val| Ok(val.atan()));
+        } else {
+            self.apply_unary_with(|x| format!("tan({})", x), |val| Ok(val.tan()));
+        }
+    }
+
+    /// Convert the displayed angle from degrees to radians
+    pub fn deg_to_rad(&mut self) {
+        self.apply_unary_with(|x| format!("rad({}\u{00B0})", x), |val| Ok(val.to_radians()));
+    }
+
+    /// Convert the displayed angle from radians to degrees
+    pub fn rad_to_deg(&mut self) {
+        self.apply_unary_with(|x| format!("deg({})", x), |val| Ok(val.to_degrees()));
+    }
+
+    /// Natural logarithm; zero and negatives are invalid. e^x after INV.
+    pub fn ln(&mut self) {
+        if std::mem::take(&mut self.inverse_pending) {
+            return self.exp();
+        }
+        self.apply_unary_with(
+            |x| format!("ln({})", x),
+            |val| {
+                if val > 0.0 {
+                    Ok(val.ln())
+                } else {
+                    Err(CalcError::InvalidInput)
+                }
+            },
+        );
+    }
+
+    /// Base-10 logarithm; zero and negatives are invalid. 10^x after INV.
+    pub fn log10(&mut self) {
+        if std::mem::take(&mut self.inverse_pending) {
+            return self.pow10();
+        }
+        self.apply_unary_with(
+            |x| format!("log({})", x),
+            |val| {
+                if val > 0.0 {
+                    Ok(val.log10())
+                } else {
+                    Err(CalcError::InvalidInput)
+                }
+            },
+        );
+    }
+
+    /// Floor integer square root of the displayed value
+    pub fn integer_sqrt(&mut self) {
+        self.apply_unary_with(
+            |x| format!("isqrt({})", x),
+            |val| {
+                if val < 0.0 || val.fract() != 0.0 || val > u64::MAX as f64 {
+                    Err(CalcError::InvalidInput)
+                } else {
+                    Ok(isqrt(val as u64) as f64)
+                }
+            },
+        );
+    }
+
+    /// Apply a one-argument function to the displayed value. `label` builds
+    /// the expression text from the formatted operand, e.g. "sqr(3)".
+    ///
+    /// With an operation pending (`9 +`), the result becomes the second
+    /// operand instead of a finished calculation: the expression line reads
+    /// "9 + √(9)", nothing is added to history, and `=` completes it.
+    fn apply_unary_with(
+        &mut self,
+        label: impl Fn(&str) -> String,
+        f: impl Fn(f64) -> Result<f64, CalcError>,
+    ) {
+        let Some(val) = self.current_value() else {
+            return;
+        };
+        match f(val) {
+            Ok(r) if r.is_finite() => {
+                let expr = label(&self.format(val));
+                match (self.first_operand, self.operator) {
+                    (Some(a), Some(op)) => {
+                        self.expression = format!(
+                            "{}{}",
+                            self.deferred_prefix(),
+                            Self::binary_expression(&self.format(a), op, &expr)
+                        );
+                        self.display = self.format(r);
+                        self.waiting_for_second = false;
+                        self.last_was_percent = false;
+                        self.computed_operand = true;
+                    }
+                    _ => self.apply_unary_result(expr, r),
+                }
+            }
+            Ok(_) => self.set_error(CalcError::Overflow),
+            Err(e) => self.set_error(e),
+        }
+    }
+
+    /// Evaluate a typed or pasted expression such as "(2+3)*4", replacing
+    /// any pending operation. Invalid input shows the error state.
+    /// `ans` refers to the last result, or 0 before anything was computed.
+    pub fn paste_expression(&mut self, text: &str) {
+        let text = text.trim();
+        self.first_operand = None;
+        self.operator = None;
+        self.deferred.clear();
+        self.waiting_for_second = false;
+        if let Some(exact) = self.evaluate_exact(text) {
+            self.expression = format!("{}{}", text, self.settings.equals_suffix);
+            self.show_exact_result(text.to_string(), exact);
+            return;
+        }
+        match self.evaluate(text) {
+            Ok(value) => {
+                self.expression = format!("{}{}", text, self.settings.equals_suffix);
+                self.show_result(text.to_string(), value);
+            }
+            Err(e) => self.set_error(e),
+        }
+    }
+
+    /// Paste a single number as if it had been typed. Values outside the
+    /// f64 range (e.g. "1e400") and non-numbers set the error state.
+    /// Thousands separators (`group_sep`) are stripped first, so
+    /// "1,234,567" pastes as 1234567 (or "1.234.567" with '.' grouping).
+    pub fn paste_number(&mut self, text: &str) {
+        let text = strip_grouping(text.trim(), self.settings.group_sep);
+        let text = text.as_str();
+        match text.parse::<f64>() {
+            Ok(value) if value.is_finite() => {
+                if self.just_computed || self.is_error() {
+                    self.clear_state();
+                }
+                self.display = if text.contains(['e', 'E']) {
+                    text.to_lowercase().replace("e+", "e").trim_start_matches('+').to_string()
+                } else {
+                    value.to_string()
+                };
+                self.waiting_for_second = false;
+                self.last_was_percent = false;
+            }
+            Ok(_) => self.set_error(CalcError::Overflow),
+            Err(_) => self.set_error(CalcError::InvalidInput),
+        }
+    }
+
+    /// Paste clipboard text: a plain number is entered like typing,
+    /// anything else is evaluated as an expression
+    pub fn paste(&mut self, text: &str) {
+        if strip_grouping(text.trim(), self.settings.group_sep).parse::<f64>().is_ok() {
+            self.paste_number(text);
+        } else {
+            self.paste_expression(text);
+        }
+    }
+
+    /// Re-evaluate the most recent history expression through `evaluate`
+    /// with the current settings (e.g. after raising precision) and show
+    /// the result. Expressions that can't be re-read on their own keep
+    /// their stored result, reformatted: ones using `ans` or variables,
+    /// the function keys' notation (`sin(9)`, `9%`), assignments and
+    /// entries written in hex. History itself is not changed. No-op when
+    /// history is empty.
+    pub fn recompute_last(&mut self) {
+        let Some(entry) = self.history.last() else {
+            return;
+        };
+        let (expr, result) = (entry.expression.clone(), entry.result.clone());
+        let readable = entry.kind == EntryKind::Computation && self.base == Base::Decimal;
+        self.first_operand = None;
+        self.operator = None;
+        self.deferred.clear();
+        self.waiting_for_second = false;
+        let exact = match readable.then(|| self.evaluate_exact(&expr)).flatten() {
+            Some(r) => Some(r),
+            None if self.rational_active() => Rational::parse(&result),
+            None => None,
+        };
+        // Only constants: names that meant something else when the entry
+        // was made (`ans`, variables) mustn't be read with today's values
+        let constants = |name: &str| Constant::from_name(name).map(Constant::value);
+        let value = match exact {
+            Some(r) => Some(r.to_f64()),
+            None => readable
+                .then(|| evaluate_with(&expr, &constants).ok())
+                .flatten()
+                .or_else(|| self.parse_value(&result))
+                .filter(|v| v.is_finite()),
+        };
+        match value.map(Ok).unwrap_or_else(|| self.evaluate(&expr)) {
+            Ok(value) => {
+                self.expression = format!("{}{}", expr, self.settings.equals_suffix);
+                self.error = None;
+                self.display = match exact {
+                    Some(r) => self.exact_text(r),
+                    None => self.format(value),
+                };
+                self.last_result = Some(value);
+                self.just_computed = true;
+                self.last_was_percent = false;
+            }
+            Err(e) => self.set_error(e),
+        }
+    }
+
+    /// Evaluate an expression with this calculator's named values: `ans`,
+    /// stored variables, then constants
+    pub fn evaluate(&self, text: &str) -> Result<f64, CalcError> {
+        let ans = self.last_result.unwrap_or(0.0);
+        evaluate_with(text, &|name| match name {
+            "ans" => Some(ans),
+            _ => self
+                .variables
+                .get(name)
+                .copied()
+                .or_else(|| Constant::from_name(name).map(Constant::value)),
+        })
+    }
+
+    /// Exact value of `text` when rational mode is on and the expression
+    /// only combines integers with `+ - * /`
+    fn evaluate_exact(&self, text: &str) -> Option<Rational> {
+        if !self.rational_active() {
+            return None;
+        }
+        eval::parse(text).ok()?.eval_rational().ok()
+    }
+
+    /// Rational mode only applies to decimal input
+    fn rational_active(&self) -> bool {
+        self.settings.rational_mode && self.base == Base::Decimal
+    }
+
+    /// The displayed value as a number, or `None` while it shows "Error".
+    /// Also accepts a fraction like "2/3" left by rational mode and hex
+    /// digits in programmer mode.
+    pub fn current_value(&self) -> Option<f64> {
+        self.parse_value(&self.display)
+    }
+
+    /// Read a number written by `format` in the current base
+    fn parse_value(&self, text: &str) -> Option<f64> {
+        if self.base == Base::Hex {
+            return i64::from_str_radix(text, 16).ok().map(|v| v as f64);
+        }
+        match text.parse::<f64>() {
+            Ok(v) => Some(v),
+            Err(_) => Rational::parse(text).map(Rational::to_f64),
+        }
+    }
+
+    /// Whether the last result had to be rounded or cut off to display it,
+    /// as with `1/3` shown as "0.3333333333"
+    pub fn last_result_rounded(&self) -> bool {
+        self.was_rounded
+    }
+
+    /// Whether the display holds a whole number, for greying out buttons
+    /// that need one. False for "Error".
+    pub fn display_is_integer(&self) -> bool {
+        self.current_value().is_some_and(|v| v.fract() == 0.0)
+    }
+
+    /// Fractions as "2/3", whole numbers formatted as usual
+    fn exact_text(&self, r: Rational) -> String {
+        if r.is_integer() {
+            self.format(r.to_f64())
+        } else {
+            r.to_string()
+        }
+    }
+
+    /// Show a unary function's result and record it in history
+    fn apply_unary_result(&mut self, expr: String, value: f64) {
+        self.expression = expr.clone();
+        self.show_result(expr, value);
+    }
+
+    /// Show a finished calculation's result and record it in history
+    fn show_result(&mut self, expr: String, value: f64) {
+        let result = self.format(value);
+        self.show_result_text(expr, value, result);
+    }
+
+    /// `show_result` for an exact rational-mode result
+    fn show_exact_result(&mut self, expr: String, r: Rational) {
+        let result = self.exact_text(r);
+        self.show_result_text(expr, r.to_f64(), result);
+    }
+
+    fn show_result_text(&mut self, expr: String, value: f64, result: String) {
+        self.error = None;
+        self.add_history(expr, result.clone());
+        self.display = result;
+        self.last_result = Some(value);
+        self.was_rounded = self.current_value() != Some(value);
+        self.just_computed = true;
+        self.last_was_percent = false;
+        self.percentage_form = false;
+        if value == 420.0 && self.settings.easter_egg {
+            self.blaze_it = true;
+            self.blaze_start = Some(std::time::Instant::now());
+        }
+    }
+
+    /// The display with thousands separators for rendering. `display`
+    /// itself stays unseparated so it can always be parsed.
+    pub fn grouped_display(&self) -> String {
+        if self.base == Base::Hex {
+            return self.display.clone();
+        }
+        match self.settings.group_sep {
+            '.' => group_digits(&self.display.replace('.', ","), '.'),
+            sep => group_digits(&self.display, sep),
+        }
+    }
+
+    /// The current value with every significant digit, for showing the
+    /// exact number behind the rounded display
+    pub fn display_full_precision(&self) -> String {
+        let value = match self.last_result {
+            Some(r) if self.just_computed && self.format(r) == self.display => r,
+            _ => match self.current_value() {
+                Some(v) => v,
+                None => return self.display.clone(),
+            },
+        };
+        let s = format!("{:.17}", value);
+        let s = s.trim_end_matches('0');
+        let s = s.trim_end_matches('.');
+        s.to_string()
+    }
+
+    /// The expression shortened to `max_chars` with a middle ellipsis
+    /// ("123…789") for display. The full expression stays in `expression`.
+    pub fn truncated_expression(&self, max_chars: usize) -> String {
+        let chars: Vec<char> = self.expression.chars().collect();
+        if chars.len() <= max_chars {
+            return self.expression.clone();
+        }
+        if max_chars == 0 {
+            return String::new();
+        }
+        let keep = max_chars - 1;
+        let tail = keep / 2;
+        let head = keep - tail;
+        let mut out: String = chars[..head].iter().collect();
+        out.push('\u{2026}');
+        out.extend(&chars[chars.len() - tail..]);
+        out
+    }
+
+    /// Restore every setting to its default and save. History is kept.
+    pub fn reset_settings(&mut self) {
+        self.settings = Settings::default();
+        save_settings(&self.settings);
+    }
+
+    /// The display holds a finished result (after `=`, a function key or a
+    /// recalled value) rather than a number being typed
+    pub fn is_result(&self) -> bool {
+        self.just_computed && !self.is_error()
+    }
+
+    /// The display is showing an error
+    pub fn is_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// The error currently shown, if any
+    pub fn last_error(&self) -> Option<CalcError> {
+        self.error
+    }
+
+    /// Format a value with the configured precision
+    pub fn format(&self, n: f64) -> String {
+        if self.base == Base::Hex {
+            return format_hex(n);
+        }
+        match self.settings.notation {
+            Notation::Standard => match self.settings.fixed_decimals {
+                Some(decimals) => format_fixed(n, decimals, self.settings.rounding),
+                None => format_rounded(n, self.settings.precision, self.settings.rounding),
+            },
+            Notation::Scientific => format_scientific(n, self.settings.precision),
+            Notation::Engineering => format_engineering(n, self.settings.precision),
+        }
+    }
+
+    fn set_error(&mut self, err: CalcError) {
+        self.error = Some(err);
+        self.display = "Error".to_string();
+        self.expression = err.to_string();
+        // The next digit starts over instead of appending to "Error"
+        self.just_computed = true;
+    }
+}
+
+impl Default for CalcApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drive `app` one key at a time, as if typed on the keypad: digits and `.`
+/// enter the number, `+ - * / × ÷ ^` are operators, `=` computes, `%` is the
+/// percent key and `C` clears. Whitespace and any other characters are ignored.
+pub fn type_sequence(app: &mut CalcApp, keys: &str) {
+    for key in keys.chars() {
+        match key {
+            '0'..='9' => app.input_digit(key),
+            '.' => app.input_dot(),
+            '+' | '-' | '*' | '/' | '^' => app.input_operator(key),
+            '\u{00D7}' => app.input_operator('*'),
+            '\u{00F7}' => app.input_operator('/'),
+            '=' => app.compute(),
+            '%' => app.percent(),
+            'C' => app.clear(),
+            _ => {}
+        }
+    }
+}
+
+/// Insert `sep` between thousands in the integer part of a number string,
+/// leaving the sign, decimals and exponent as typed ("-1234.5" -> "-1,234.5").
+/// Text that doesn't start with digits, like "Error", is returned unchanged.
+pub fn group_digits(s: &str, sep: char) -> String {
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", s),
+    };
+    let int_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let (int_part, tail) = rest.split_at(int_len);
+    if int_part.is_empty() {
+        return s.to_string();
+    }
+    let mut out = String::from(sign);
+    for (i, c) in int_part.chars().enumerate() {
+        if i > 0 && (int_len - i) % 3 == 0 {
+            out.push(sep);
+        }
+        out.push(c);
+    }
+    out.push_str(tail);
+    out
+}
+
+/// Undo `group_digits` on typed or pasted text: remove `group_sep` and, when
+/// it is '.', read ',' as the decimal point ("1.234,5" -> "1234.5")
+pub fn strip_grouping(text: &str, group_sep: char) -> String {
+    let stripped: String = text.chars().filter(|&c| c != group_sep).collect();
+    if group_sep == '.' {
+        stripped.replace(',', ".")
+    } else {
+        stripped
+    }
+}
+
+/// A variable name: a letter followed by letters, digits or `_`. Names
+/// `evaluate` already reads, `ans` and the constants (`pi`, `e`), are
+/// reserved, in any case.
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let lower = name.to_ascii_lowercase();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && lower != "ans"
+        && Constant::from_name(&lower).is_none()
+}
+
+/// A history entry as LaTeX, e.g. `\sqrt{2} = 1.4142135624` for "√(2)".
+/// Understands the expressions the app writes: `sqr()`, `√()`, `1/()`,
+/// `e^`/`10^`, named functions like `ln()` and the `× ÷ −` operators.
+/// Fraction results from rational mode become `\frac`.
+pub fn to_latex(entry: &HistoryEntry) -> String {
+    let result = match entry.result.split_once('/') {
+        Some((num, den)) => format!("\\frac{{{}}}{{{}}}", num, den),
+        None => entry.result.clone(),
+    };
+    format!("{} = {}", latex_expr(&entry.expression), result)
+}
+
+fn latex_expr(expr: &str) -> String {
+    const WRAPPERS: [(&str, &str, &str); 2] =
+        [("\u{221A}(", "\\sqrt{", "}"), ("1/(", "\\frac{1}{", "}")];
+    const FUNCTIONS: [&str; 8] = ["asin", "acos", "atan", "sin", "cos", "tan", "ln", "log"];
+    let mut out = String::new();
+    // Where the operand being written began, for a binary `^`
+    let mut operand_start = 0;
+    let mut rest = expr;
+    'scan: while let Some(c) = rest.chars().next() {
+        // `2 ^ 10` from the power key: both sides braced, the exponent
+        // running through any further powers (`2 ^ 3 ^ 2`)
+        if let Some(after) = rest.strip_prefix(" ^ ") {
+            let end = power_exponent_end(after);
+            let base = out.split_off(operand_start);
+            out.push_str(&format!("{{{}}}^{{{}}}", base, latex_expr(&after[..end])));
+            rest = &after[end..];
+            continue;
+        }
+        if let Some((inner, after)) = rest.strip_prefix("sqr(").and_then(split_group) {
+            out.push_str(&format!("{}^{{2}}", wrap_negative(&latex_expr(inner))));
+            rest = after;
+            continue;
+        }
+        for (prefix, open, close) in WRAPPERS {
+            if let Some((inner, after)) = rest.strip_prefix(prefix).and_then(split_group) {
+                out.push_str(&format!("{}{}{}", open, latex_expr(inner), close));
+                rest = after;
+                continue 'scan;
+            }
+        }
+        for name in FUNCTIONS {
+            if let Some((inner, after)) =
+                rest.strip_prefix(name).and_then(|r| r.strip_prefix('(')).and_then(split_group)
+            {
+                let name = match name {
+                    "asin" | "acos" | "atan" => format!("\\{}", name.replacen('a', "arc", 1)),
+                    _ => format!("\\{}", name),
+                };
+                out.push_str(&format!("{}({})", name, latex_expr(inner)));
+                rest = after;
+                continue 'scan;
+            }
+        }
+        // `e^2`, `10^-3`: the exponent runs to the next space
+        if let Some(pos) = rest.find('^').filter(|&p| matches!(&rest[..p], "e" | "10")) {
+            let exponent = &rest[pos + 1..];
+            let end = exponent.find(' ').unwrap_or(exponent.len());
+            out.push_str(&format!("{}^{{{}}}", &rest[..pos], &exponent[..end]));
+            rest = &exponent[end..];
+            continue;
+        }
+        match c {
+            '\u{00D7}' => out.push_str("\\times"),
+            '\u{00F7}' => out.push_str("\\div"),
+            '\u{2212}' => out.push('-'),
+            _ => out.push(c),
+        }
+        if c == ' ' {
+            operand_start = out.len();
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Length of the exponent at the start of `s`: one operand, ending at a
+/// space outside parentheses, plus any `^` chained after it
+fn power_exponent_end(s: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ' ' if depth == 0 => {
+                return match s[i..].strip_prefix(" ^ ") {
+                    Some(more) => i + 3 + power_exponent_end(more),
+                    None => i,
+                };
+            }
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+/// Split "inner) tail" at the parenthesis closing an already-opened group
+fn split_group(s: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some((&s[..i], &s[i + 1..])),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split RFC 4180 text into records of fields. Quoted fields may hold
+/// commas, newlines and doubled quotes. Returns the well-formed records and
+/// a count of malformed ones (stray or unterminated quotes); blank lines
+/// are ignored.
+fn parse_csv(text: &str) -> (Vec<Vec<String>>, usize) {
+    let mut records = Vec::new();
+    let mut malformed = 0;
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let (mut in_quotes, mut quoted, mut bad) = (false, false, false);
+    let mut chars = text.chars().peekable();
+    loop {
+        let c = chars.next();
+        if in_quotes {
+            match c {
+                Some('"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                Some('"') => in_quotes = false,
+                Some(c) => field.push(c),
+                None => {
+                    malformed += 1;
+                    break;
+                }
+            }
+            continue;
+        }
+        match c {
+            Some('"') if field.is_empty() && !quoted => (in_quotes, quoted) = (true, true),
+            Some(',') => {
+                record.push(std::mem::take(&mut field));
+                quoted = false;
+            }
+            Some('\r') if chars.peek() == Some(&'\n') => {}
+            Some('\n') | None => {
+                if !record.is_empty() || !field.is_empty() || quoted {
+                    record.push(std::mem::take(&mut field));
+                    if bad {
+                        malformed += 1;
+                    } else {
+                        records.push(std::mem::take(&mut record));
+                    }
+                }
+                record.clear();
+                (quoted, bad) = (false, false);
+                if c.is_none() {
+                    break;
+                }
+            }
+            // Text after a closing quote, or a quote inside a bare field
+            Some(c) => {
+                bad |= quoted || c == '"';
+                field.push(c);
+            }
+        }
+    }
+    (records, malformed)
+}
+
+/// Width for the history panel: enough for the longest expression or
+/// "= result" line at `char_px` per character, clamped to `min..=max`
+pub fn history_panel_width(entries: &[HistoryEntry], min: f32, max: f32, char_px: f32) -> f32 {
+    let longest = entries
+        .iter()
+        .map(|e| e.expression.chars().count().max(e.result.chars().count() + 2))
+        .max()
+        .unwrap_or(0);
+    (longest as f32 * char_px).clamp(min, max)
+}
+
+/// Money formatting with grouping and exactly `decimals` places:
+/// `format_currency(-1234.5, "$", 2)` is "-$1,234.50". Amounts that round to
+/// zero drop the minus sign.
+pub fn format_currency(n: f64, symbol: &str, decimals: usize) -> String {
+    let amount = format!("{:.*}", decimals, n.abs());
+    let negative = n < 0.0 && amount.chars().any(|c| c.is_ascii_digit() && c != '0');
+    let sign = if negative { "-" } else { "" };
+    format!("{}{}{}", sign, symbol, group_digits(&amount, ','))
+}
+
+/// Font size for the main display, shrinking as the text gets longer
+pub fn display_font_size(len: usize) -> f32 {
+    if len > DISPLAY_SMALL_LEN {
+        DISPLAY_FONT_SMALL
+    } else if len > DISPLAY_MEDIUM_LEN {
+        DISPLAY_FONT_MEDIUM
+    } else {
+        DISPLAY_FONT_LARGE
+    }
+}
+
+/// Floor of the square root of `n`, exact for the full `u64` range
+pub fn isqrt(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    // The float estimate can be off by one for large inputs; nudge it into place
+    let n = n as u128;
+    let mut x = (n as f64).sqrt() as u128;
+    while x * x > n {
+        x -= 1;
+    }
+    while (x + 1) * (x + 1) <= n {
+        x += 1;
+    }
+    x as u64
+}
+
+/// Gross amount after adding `rate_percent` tax to a net amount
+pub fn add_tax(amount: f64, rate_percent: f64) -> f64 {
+    amount * (1.0 + rate_percent / 100.0)
+}
+
+/// Net amount that `rate_percent` tax was added to in order to reach `gross`
+pub fn remove_tax(gross: f64, rate_percent: f64) -> f64 {
+    gross / (1.0 + rate_percent / 100.0)
+}
+
+/// Price after taking `percent_off` off; discounts above 100% give 0
+pub fn apply_discount(price: f64, percent_off: f64) -> f64 {
+    (price * (1.0 - percent_off / 100.0)).max(0.0)
+}
+
+/// `value` rounded to the nearest multiple of `multiple` (47 to the nearest
+/// 5 is 45, 48 is 50; halfway rounds away from zero). `None` when
+/// `multiple` is zero.
+pub fn round_to_multiple(value: f64, multiple: f64) -> Option<f64> {
+    if multiple == 0.0 {
+        None
+    } else {
+        Some((value / multiple).round() * multiple)
+    }
+}
+
+/// `value` raised by `pct` percent (markup): 100 +10% is 110
+pub fn increase_by_percent(value: f64, pct: f64) -> f64 {
+    value + value * pct / 100.0
+}
+
+/// `value` lowered by `pct` percent (markdown): 100 -10% is 90. Unlike
+/// `apply_discount` the result is not clamped at zero.
+pub fn decrease_by_percent(value: f64, pct: f64) -> f64 {
+    value - value * pct / 100.0
+}
+
+/// What percent `part` is of `whole`; `None` when `whole` is zero
+pub fn percent_of(part: f64, whole: f64) -> Option<f64> {
+    if whole == 0.0 {
+        None
+    } else {
+        Some(part / whole * 100.0)
+    }
+}
+
+/// Tip on a bill: `(tip_amount, grand_total)`. Show the parts with
+/// `format_number` like any other result.
+pub fn tip_breakdown(total: f64, tip_percent: f64) -> (f64, f64) {
+    let tip = total * tip_percent / 100.0;
+    (tip, total + tip)
+}
+
+/// Sum of comma-separated numbers ("1, 2, 3"). Blank input sums to 0;
+/// an empty or non-numeric item is `InvalidInput`.
+pub fn sum_list(input: &str) -> Result<f64, CalcError> {
+    let sum: f64 = parse_list(input)?.iter().sum();
+    if sum.is_finite() {
+        Ok(sum)
+    } else {
+        Err(CalcError::Overflow)
+    }
+}
+
+/// Mean of comma-separated numbers; blank input is `DivisionByZero`
+pub fn average_list(input: &str) -> Result<f64, CalcError> {
+    let count = parse_list(input)?.len();
+    apply_op(sum_list(input)?, '/', count as f64)
+}
+
+fn parse_list(input: &str) -> Result<Vec<f64>, CalcError> {
+    if input.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    input
+        .split(',')
+        .map(|item| match item.trim().parse::<f64>() {
+            Ok(v) if v.is_finite() => Ok(v),
+            _ => Err(CalcError::InvalidInput),
+        })
+        .collect()
+}
+
+/// Symmetric percentage difference, `|a - b|` relative to the mean of `a`
+/// and `b`. Unlike percent change, swapping the arguments gives the same
+/// answer. `None` when `a + b` is zero.
+pub fn percent_difference(a: f64, b: f64) -> Option<f64> {
+    let mean = (a + b) / 2.0;
+    if mean == 0.0 {
+        None
+    } else {
+        Some((a - b).abs() / mean * 100.0)
+    }
+}
+
+/// Share of `budget` left after spending `spent`, as a percentage.
+/// Overspending gives 0 rather than a negative value; `None` when `budget`
+/// is zero.
+pub fn percent_remaining(spent: f64, budget: f64) -> Option<f64> {
+    if budget == 0.0 {
+        None
+    } else {
+        Some(((budget - spent) / budget * 100.0).max(0.0))
+    }
+}
+
+/// Split decimal hours into hours, minutes and seconds: 1.5 is (1, 30, 0).
+/// A negative duration has every component negative, so
+/// `hms_to_hours` reverses it exactly: -1.5 is (-1, -30, -0).
+pub fn hours_to_hms(hours: f64) -> (i64, i64, f64) {
+    let sign = if hours < 0.0 { -1.0 } else { 1.0 };
+    // Round to microseconds so 0.1 hours is 6 minutes, not 5:59.999…
+    let total = (hours.abs() * 3600.0 * 1e6).round() / 1e6;
+    let h = (total / 3600.0).floor();
+    let m = ((total - h * 3600.0) / 60.0).floor();
+    let s = total - h * 3600.0 - m * 60.0;
+    ((sign * h) as i64, (sign * m) as i64, sign * s)
+}
+
+/// Decimal hours from hours, minutes and seconds
+pub fn hms_to_hours(h: i64, m: i64, s: f64) -> f64 {
+    h as f64 + m as f64 / 60.0 + s / 3600.0
+}
+
+/// Decimal hours as "h:mm:ss", e.g. "1:30:00" or "-0:45:30.5"
+pub fn format_hms(hours: f64) -> String {
+    let (h, m, s) = hours_to_hms(hours.abs());
+    let sign = if hours < 0.0 { "-" } else { "" };
+    let whole = s.trunc() as i64;
+    let fraction = format_number(s.fract());
+    let fraction = fraction.strip_prefix('0').unwrap_or("");
+    format!("{}{}:{:02}:{:02}{}", sign, h, m, whole, fraction)
+}
+
+/// Future value of `principal` compounded `times_per_year` times a year.
+/// Returns `None` when `times_per_year` is zero.
+pub fn compound_interest(
+    principal: f64,
+    annual_rate_percent: f64,
+    times_per_year: u32,
+    years: f64,
+) -> Option<f64> {
+    if times_per_year == 0 {
+        return None;
+    }
+    let n = times_per_year as f64;
+    Some(principal * (1.0 + annual_rate_percent / 100.0 / n).powf(n * years))
+}
+
+/// Format a result for display: integers without a decimal point, other
+/// values rounded to at most 10 decimals with trailing zeros trimmed.
+///
+/// For any finite `x`, formatting is stable under a parse round-trip:
+/// `format_number(format_number(x).parse().unwrap()) == format_number(x)`.
+/// The output already has at most 10 decimals, so re-rounding it changes
+/// nothing. Digits beyond the 10th decimal are lost by the first call.
+/// Nonzero values too small for 10 decimals switch to scientific notation
+/// ("-1e-11") rather than printing as "0"; that form round-trips too.
+pub fn format_number(n: f64) -> String {
+    format_number_with(n, DEFAULT_PRECISION)
+}
+
+/// `format_number` with at most `precision` decimals
+pub fn format_number_with(n: f64, precision: usize) -> String {
+    if n.is_nan() || n.is_infinite() {
+        return "Error".to_string();
+    }
+    if n == n.floor() && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        let s = format!("{:.*}", precision, n);
+        let s = s.trim_end_matches('0');
+        let s = s.trim_end_matches('.');
+        if s == "0" || s == "-0" {
+            // Too small for `precision` decimals but not zero: don't claim it is
+            return format_scientific(n, precision);
+        }
+        s.to_string()
+    }
+}
+
+/// `n` in scientific notation with `sig` significant digits and trailing
+/// zeros dropped: 12345678 with 5 digits is "1.2346e7"
+pub fn format_scientific(n: f64, sig: usize) -> String {
+    if !n.is_finite() {
+        return "Error".to_string();
+    }
+    if n == 0.0 {
+        return "0".to_string();
+    }
+    let s = format!("{:.*e}", sig.max(1) - 1, n);
+    let (mantissa, exp) = s.split_once('e').unwrap_or((&s, "0"));
+    format!("{}e{}", trim_decimals(mantissa), exp)
+}
+
+/// `n` with an exponent that's a multiple of 3 and `sig` significant
+/// digits: 12345678 with 5 digits is "12.346e6", 0.0001234 is "123.4e-6".
+/// The exponent is left off when it would be 0.
+pub fn format_engineering(n: f64, sig: usize) -> String {
+    if !n.is_finite() {
+        return "Error".to_string();
+    }
+    if n == 0.0 {
+        return "0".to_string();
+    }
+    let sig = sig.max(1) as i32;
+    let mut exp = n.abs().log10().floor() as i32;
+    // Rounding to `sig` digits can carry into the next power (9999.7 -> 10000)
+    let scaled = n / 10f64.powi(exp - sig + 1);
+    if scaled.round().abs() >= 10f64.powi(sig) {
+        exp += 1;
+    }
+    let eng = exp.div_euclid(3) * 3;
+    let decimals = (sig - (exp - eng + 1)).max(0) as usize;
+    let mantissa = format!("{:.*}", decimals, n / 10f64.powi(eng));
+    let mantissa = trim_decimals(&mantissa);
+    if eng == 0 {
+        mantissa.to_string()
+    } else {
+        format!("{}e{}", mantissa, eng)
+    }
+}
+
+/// Drop trailing zeros after a decimal point, and the point if bare
+fn trim_decimals(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+/// Binding strength of an operator in algebraic mode: `^`, then `×` and
+/// `÷`, before everything else
+fn precedence(op: char) -> u8 {
+    match op {
+        '^' => 3,
+        '*' | '/' => 2,
+        _ => 1,
+    }
+}
+
+/// Whether a pending `left` operation must be applied before `right` is
+/// entered: it binds tighter, or as tightly and `right` isn't `^`. Powers
+/// group right to left, so `2 ^ 3 ^ 2` is `2 ^ 9`.
+fn binds_before(left: char, right: char) -> bool {
+    precedence(left) > precedence(right) || (precedence(left) == precedence(right) && right != '^')
+}
+
+/// asin/acos, rejecting input outside [-1, 1]
+fn checked_arc(f: fn(f64) -> f64) -> impl Fn(f64) -> Result<f64, CalcError> {
+    move |val| {
+        if (-1.0..=1.0).contains(&val) {
+            Ok(f(val))
+        } else {
+            Err(CalcError::InvalidInput)
+        }
+    }
+}
+
+fn wrap_negative(text: &str) -> String {
+    if text.starts_with('-') {
+        format!("({})", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Round `n` to `digits` decimals using `mode`. Values too large to scale
+/// are returned unchanged.
+pub fn round_with(n: f64, digits: usize, mode: RoundingMode) -> f64 {
+    let factor = 10f64.powi(digits.min(i32::MAX as usize) as i32);
+    let x = n * factor;
+    if !x.is_finite() || x.fract() == 0.0 {
+        return n;
+    }
+    let rounded = match mode {
+        RoundingMode::HalfUp => x.round(),
+        RoundingMode::HalfEven => {
+            if x.fract().abs() == 0.5 {
+                2.0 * (x / 2.0).round()
+            } else {
+                x.round()
+            }
+        }
+        RoundingMode::Down => x.trunc(),
+        RoundingMode::Up => x.trunc() + x.signum(),
+    };
+    rounded / factor
+}
+
+/// Uppercase hex of `n` truncated toward zero ("-FF"); out-of-range values
+/// saturate at the `i64` limits
+pub fn format_hex(n: f64) -> String {
+    if n.is_nan() || n.is_infinite() {
+        return "Error".to_string();
+    }
+    let v = n as i64;
+    if v < 0 {
+        format!("-{:X}", v.unsigned_abs())
+    } else {
+        format!("{:X}", v)
+    }
+}
+
+/// `format_number_with` after rounding the value with `mode`. Nonzero
+/// values that round to zero are shown in scientific notation instead.
+pub fn format_rounded(n: f64, precision: usize, mode: RoundingMode) -> String {
+    let rounded = round_with(n, precision, mode);
+    if rounded == 0.0 && n != 0.0 {
+        return format_scientific(n, precision);
+    }
+    format_number_with(rounded, precision)
+}
+
+/// `n` rounded with `mode` to exactly `decimals` decimals, keeping
+/// trailing zeros: 5 with 2 decimals is "5.00"
+pub fn format_fixed(n: f64, decimals: usize, mode: RoundingMode) -> String {
+    if n.is_nan() || n.is_infinite() {
+        return "Error".to_string();
+    }
+    // Adding 0.0 turns a negative zero into "0.00" rather than "-0.00"
+    let rounded = round_with(n, decimals, mode) + 0.0;
+    format!("{:.*}", decimals, rounded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_digit() {
+        let mut app = CalcApp::new();
+        assert_eq!(app.display, "0");
+
+        app.input_digit('5');
+        assert_eq!(app.display, "5");
+
+        app.input_digit('3');
+        assert_eq!(app.display, "53");
+    }
+
+    #[test]
+    fn test_input_digit_replaces_zero() {
+        let mut app = CalcApp::new();
+        app.input_digit('7');
+        assert_eq!(app.display, "7");
+    }
+
+    #[test]
+    fn test_input_dot() {
+        let mut app = CalcApp::new();
+        app.input_dot();
+        assert_eq!(app.display, "0.");
+
+        app.input_digit('5');
+        assert_eq!(app.display, "0.5");
+
+        // Second dot should not be added
+        app.input_dot();
+        assert_eq!(app.display, "0.5");
+    }
+
+    #[test]
+    fn test_addition() {
+        let mut app = CalcApp::new();
+        app.input_digit('2');
+        app.input_operator('+');
+        assert_eq!(app.first_operand, Some(2.0));
+        assert_eq!(app.operator, Some('+'));
+
+        app.input_digit('3');
+        app.compute();
+        assert_eq!(app.display, "5");
+    }
+
+    #[test]
+    fn test_subtraction() {
+        let mut app = CalcApp::new();
+        app.input_digit('1');
+        app.input_digit('0');
+        app.input_operator('-');
+
+        app.input_digit('3');
+        app.compute();
+        assert_eq!(app.display, "7");
+    }
+
+    #[test]
+    fn test_multiplication() {
+        let mut app = CalcApp::new();
+        app.input_digit('4');
+        app.input_operator('*');
+
+        app.input_digit('5');
+        app.compute();
+        assert_eq!(app.display, "20");
+    }
+
+    #[test]
+    fn test_division() {
+        let mut app = CalcApp::new();
+        app.input_digit('2');
+        app.input_digit('0');
+        app.input_operator('/');
+
+        app.input_digit('4');
+        app.compute();
+        assert_eq!(app.display, "5");
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let mut app = CalcApp::new();
+        app.input_digit('1');
+        app.input_operator('/');
+
+        app.input_digit('0');
+        app.compute();
+        assert_eq!(app.display, "Error");
+        assert_eq!(app.expression, "Cannot divide by zero");
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.input_operator('+');
+        app.clear();
+
+        assert_eq!(app.display, "0");
+        assert_eq!(app.expression, "");
+        assert_eq!(app.first_operand, None);
+        assert_eq!(app.operator, None);
+    }
+
+    #[test]
+    fn test_clear_entry() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.clear_entry();
+        assert_eq!(app.display, "0");
+    }
+
+    #[test]
+    fn test_backspace() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.input_digit('3');
+        app.backspace();
+        assert_eq!(app.display, "5");
+
+        app.backspace();
+        assert_eq!(app.display, "0");
+    }
+
+    #[test]
+    fn test_toggle_sign_positive() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.toggle_sign();
+        assert_eq!(app.display, "-5");
+    }
+
+    #[test]
+    fn test_toggle_sign_negative() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.toggle_sign();
+        app.toggle_sign();
+        assert_eq!(app.display, "5");
+    }
+
+    #[test]
+    fn test_toggle_sign_on_zero_does_nothing() {
+        let mut app = CalcApp::new();
+        app.toggle_sign();
+        assert_eq!(app.display, "0");
+    }
+
+    #[test]
+    fn test_percent() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.input_digit('0');
+        app.percent();
+        assert_eq!(app.display, "0.5");
+    }
+
+    #[test]
+    fn test_format_number_integer() {
+        assert_eq!(format_number(42.0), "42");
+        assert_eq!(format_number(-15.0), "-15");
+    }
+
+    #[test]
+    fn test_format_number_decimal() {
+        assert_eq!(format_number(3.25), "3.25");
+        assert_eq!(format_number(0.5), "0.5");
+    }
+
+    #[test]
+    fn test_format_number_trailing_zeros() {
+        assert_eq!(format_number(1.5000), "1.5");
+        assert_eq!(format_number(2.0), "2");
+    }
+
+    #[test]
+    fn test_format_number_large_number() {
+        assert_eq!(format_number(1000000.0), "1000000");
+    }
+
+    #[test]
+    fn test_chained_operations() {
+        let mut app = CalcApp::new();
+        // 2 + 3 * 4 should compute left-to-right as (2 + 3) * 4 = 20
+        app.input_digit('2');
+        app.input_operator('+');
+        app.input_digit('3');
+        app.compute(); // 2 + 3 = 5
+        assert_eq!(app.display, "5");
+
+        app.input_operator('*');
+        app.input_digit('4');
+        app.compute(); // 5 * 4 = 20
+        assert_eq!(app.display, "20");
+    }
+
+    #[test]
+    fn test_history_round_trip_with_tab_and_newline() {
+        let path = std::env::temp_dir().join("calc_test_history_escape.txt");
+        let history = vec![
+            HistoryEntry::new("a\tb", "line1\nline2"),
+            HistoryEntry::new("back\\slash", "8"),
+        ];
+        save_history_to(&path, &history);
+        let loaded = load_history_from(&path);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].expression, "a\tb");
+        assert_eq!(loaded[0].result, "line1\nline2");
+        assert_eq!(loaded[1].expression, "back\\slash");
+        assert_eq!(loaded[1].result, "8");
+    }
+
+    #[test]
+    fn test_isqrt() {
+        assert_eq!(isqrt(16), 4);
+        assert_eq!(isqrt(15), 3);
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(1_000_000_000_000_000_000), 1_000_000_000);
+        assert_eq!(isqrt(u64::MAX), 4_294_967_295);
+    }
+
+    #[test]
+    fn test_integer_sqrt() {
+        let mut app = CalcApp::new();
+        app.input_digit('1');
+        app.input_digit('7');
+        app.integer_sqrt();
+        assert_eq!(app.display, "4");
+        assert_eq!(app.expression, "isqrt(17)");
+    }
+
+    #[test]
+    fn test_integer_sqrt_rejects_non_integers() {
+        let mut app = CalcApp::new();
+        app.input_digit('2');
+        app.input_dot();
+        app.input_digit('5');
+        app.integer_sqrt();
+        assert_eq!(app.display, "Error");
+
+        let mut app = CalcApp::new();
+        app.input_digit('4');
+        app.toggle_sign();
+        app.integer_sqrt();
+        assert_eq!(app.display, "Error");
+    }
+
+    #[test]
+    fn test_add_tax() {
+        assert_eq!(format_number(add_tax(100.0, 20.0)), "120");
+        assert_eq!(format_number(add_tax(50.0, 0.0)), "50");
+    }
+
+    #[test]
+    fn test_remove_tax() {
+        assert_eq!(format_number(remove_tax(120.0, 20.0)), "100");
+        assert_eq!(format_number(remove_tax(100.0, -100.0)), "Error");
+    }
+
+    #[test]
+    fn test_compound_interest() {
+        let value = compound_interest(1000.0, 5.0, 1, 2.0).unwrap();
+        assert!((value - 1102.5).abs() < 1e-9);
+        assert_eq!(format_number(value), "1102.5");
+
+        let monthly = compound_interest(1000.0, 12.0, 12, 1.0).unwrap();
+        assert!((monthly - 1126.825030131969).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_compound_interest_zero_periods() {
+        assert_eq!(compound_interest(1000.0, 5.0, 0, 2.0), None);
+    }
+
+    #[test]
+    fn test_display_full_precision() {
+        let mut app = CalcApp::new();
+        app.input_digit('1');
+        app.input_operator('/');
+        app.input_digit('3');
+        app.compute();
+        assert_eq!(app.display, "0.3333333333");
+        assert_eq!(app.display_full_precision(), "0.33333333333333331");
+
+        app.input_digit('5');
+        assert_eq!(app.display_full_precision(), "5");
+    }
+
+    #[test]
+    fn test_leading_minus_starts_negative_number() {
+        let mut app = CalcApp::new();
+        app.input_operator('-');
+        assert_eq!(app.display, "-0");
+        assert_eq!(app.operator, None);
+
+        app.input_digit('5');
+        assert_eq!(app.display, "-5");
+        app.compute();
+        assert_eq!(app.display, "-5");
+
+        app.input_operator('+');
+        app.input_digit('2');
+        app.compute();
+        assert_eq!(app.display, "-3");
+    }
+
+    #[test]
+    fn test_minus_after_operand_still_subtracts() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.input_operator('-');
+        app.input_digit('3');
+        app.compute();
+        assert_eq!(app.display, "2");
+    }
+
+    #[test]
+    fn test_backspace_negative_single_digit() {
+        let mut app = CalcApp::new();
+        app.input_operator('-');
+        app.input_digit('5');
+        app.backspace();
+        assert_eq!(app.display, "0");
+    }
+
+    #[test]
+    fn test_memory_slots() {
+        let mut app = CalcApp::new();
+        app.memory = [0.0; MEMORY_SLOTS];
+        for (slot, d) in ['1', '2', '3', '4'].into_iter().enumerate() {
+            app.clear();
+            app.input_digit(d);
+            app.memory_add(slot);
+        }
+        // M+ accumulates: M2 = 2 + 4
+        app.memory_add(1);
+        assert_eq!(app.memory_values(), [1.0, 6.0, 3.0, 4.0]);
+
+        app.clear();
+        app.memory_recall(2);
+        assert_eq!(app.display, "3");
+        app.memory_recall(1);
+        assert_eq!(app.display, "6");
+
+        app.memory_clear(1);
+        assert_eq!(app.memory_values(), [1.0, 0.0, 3.0, 4.0]);
+
+        // Out-of-range slots are ignored
+        app.memory_add(MEMORY_SLOTS);
+        app.memory_recall(MEMORY_SLOTS);
+        assert_eq!(app.display, "6");
+    }
+
+    #[test]
+    fn test_memory_recall_as_second_operand() {
+        let mut app = CalcApp::new();
+        app.memory = [0.0; MEMORY_SLOTS];
+        app.memory[0] = 7.0;
+        app.input_digit('3');
+        app.input_operator('+');
+        app.memory_recall(0);
+        app.compute();
+        assert_eq!(app.display, "10");
+    }
+
+    #[test]
+    fn test_memory_round_trip() {
+        let path = std::env::temp_dir().join("calc_test_memory.txt");
+        save_memory_to(&path, &[1.5, -2.0, 0.0, 1e20]);
+        let loaded = load_memory_from(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded, [1.5, -2.0, 0.0, 1e20]);
+    }
+
+    #[test]
+    fn test_apply_op() {
+        assert_eq!(apply_op(2.0, '+', 3.0), Ok(5.0));
+        assert_eq!(apply_op(2.0, '-', 3.0), Ok(-1.0));
+        assert_eq!(apply_op(2.0, '*', 3.0), Ok(6.0));
+        assert_eq!(apply_op(3.0, '/', 2.0), Ok(1.5));
+        assert_eq!(apply_op(1.0, '/', 0.0), Err(CalcError::DivisionByZero));
+        assert_eq!(apply_op(1.0, '?', 2.0), Err(CalcError::UnknownOperator('?')));
+        assert_eq!(apply_op(f64::MAX, '*', 2.0), Err(CalcError::Overflow));
+    }
+
+    #[test]
+    fn test_compute_overflow_is_error() {
+        let mut app = CalcApp::new();
+        app.display = "1e308".to_string();
+        app.input_operator('*');
+        app.input_digit('9');
+        app.compute();
+        assert_eq!(app.display, "Error");
+        assert_eq!(app.expression, "Overflow");
+    }
+
+    #[test]
+    fn test_format_number_round_trip_is_stable() {
+        let tricky = [
+            0.1,
+            1.0 / 3.0,
+            2.0 / 3.0,
+            0.1 + 0.2,
+            1e-11,
+            -1e-11,
+            -0.0,
+            123_456_789.123_456_79,
+            1e15 + 0.5,
+            1e20,
+            -98_765.432_109_876_54,
+        ];
+        for x in tricky {
+            let once = format_number(x);
+            let twice = format_number(once.parse().unwrap());
+            assert_eq!(once, twice, "unstable for {x:e}");
+        }
+        assert_eq!(format_number(-1e-11), "-1e-11");
+    }
+
+    #[test]
+    fn test_input_double_zero() {
+        let mut app = CalcApp::new();
+        app.input_double_zero();
+        assert_eq!(app.display, "0");
+
+        app.input_digit('5');
+        app.input_double_zero();
+        assert_eq!(app.display, "500");
+
+        app.input_operator('+');
+        app.input_digit('1');
+        app.compute();
+        assert_eq!(app.display, "501");
+        app.input_double_zero();
+        assert_eq!(app.display, "0");
+        assert_eq!(app.expression, "");
+    }
+
+    #[test]
+    fn test_percent_of_pending_addend() {
+        let mut app = CalcApp::new();
+        app.input_digit('2');
+        app.input_digit('0');
+        app.input_digit('0');
+        app.input_operator('+');
+        app.input_digit('1');
+        app.input_digit('0');
+        app.percent();
+        assert_eq!(app.display, "20");
+        app.compute();
+        assert_eq!(app.display, "220");
+    }
+
+    #[test]
+    fn test_percent_chain() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.input_digit('0');
+        app.percent();
+        assert!(app.last_was_percent);
+        app.input_operator('+');
+        assert!(!app.last_was_percent);
+        app.input_digit('1');
+        app.input_digit('0');
+        app.percent();
+        assert_eq!(app.display, "0.05");
+        app.compute();
+        assert_eq!(app.display, "0.55");
+    }
+
+    #[test]
+    fn test_percent_with_multiplication() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.input_digit('0');
+        app.input_operator('*');
+        app.input_digit('1');
+        app.input_digit('0');
+        app.percent();
+        app.compute();
+        assert_eq!(app.display, "5");
+    }
+
+    #[test]
+    fn test_percent_not_applied_twice() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.input_digit('0');
+        app.percent();
+        app.percent();
+        assert_eq!(app.display, "0.5");
+
+        app.input_digit('3');
+        assert_eq!(app.display, "3");
+    }
+
+    #[test]
+    fn test_precision_setting() {
+        let mut app = CalcApp::new();
+        app.settings.precision = 3;
+        app.input_digit('2');
+        app.input_operator('/');
+        app.input_digit('3');
+        app.compute();
+        assert_eq!(app.display, "0.667");
+        assert_eq!(format_number_with(2.0 / 3.0, 3), "0.667");
+    }
+
+    #[test]
+    fn test_max_history_setting() {
+        let mut app = CalcApp::new();
+        app.settings.max_history = 2;
+        for d in ['1', '2', '3'] {
+            app.input_digit(d);
+            app.input_operator('+');
+            app.input_digit('1');
+            app.compute();
+        }
+        assert_eq!(app.history.len(), 2);
+        assert_eq!(app.history[1].expression, "3 + 1");
+    }
+
+    #[test]
+    fn test_reset_settings_keeps_history() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.add_history("1 + 1".to_string(), "2".to_string());
+        app.settings.max_history = 3;
+        app.settings.precision = 2;
+        app.settings.easter_egg = false;
+        app.settings.sound = false;
+
+        app.reset_settings();
+        assert_eq!(app.settings, Settings::default());
+        assert_eq!(app.settings.max_history, 10);
+        assert_eq!(app.settings.precision, DEFAULT_PRECISION);
+        assert_eq!(app.history.len(), 1);
+        assert_eq!(app.history[0].result, "2");
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let path = std::env::temp_dir().join("calc_test_settings.txt");
+        let settings = Settings {
+            max_history: 25,
+            precision: 4,
+            rounding: RoundingMode::Down,
+            fixed_decimals: Some(2),
+            rational_mode: true,
+            persist_history: false,
+            autosave: false,
+            group_sep: '.',
+            algebraic: true,
+            notation: Notation::Engineering,
+            layout: Layout::Scientific,
+            easter_egg: false,
+            sound: true,
+            blaze_duration: 4.5,
+            equals_suffix: " =\n".to_string(),
+            log_path: Some(PathBuf::from("logs/calc.log")),
+        };
+        save_settings_to(&path, &settings);
+        let loaded = load_settings_from(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded, settings);
+    }
+
+    #[test]
+    fn test_history_entry_formatting() {
+        let entry = HistoryEntry::new("5 + 3", "8");
+        assert_eq!(entry.to_string(), "5 + 3 = 8");
+        assert_eq!(
+            format!("{:?}", entry),
+            r#"HistoryEntry { expression: "5 + 3", result: "8", kind: Computation }"#
+        );
+    }
+
+    #[test]
+    fn test_history_entry_equality() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.input_digit('5');
+        app.input_operator('+');
+        app.input_digit('3');
+        app.compute();
+
+        let expected = HistoryEntry::new("5 + 3", "8");
+        assert_eq!(app.history[0], expected);
+        assert_ne!(
+            app.history[0],
+            HistoryEntry::new("5 + 3", "9")
+        );
+    }
+
+    #[test]
+    fn test_apply_discount() {
+        assert_eq!(format_number(apply_discount(80.0, 25.0)), "60");
+        assert_eq!(format_number(apply_discount(50.0, 150.0)), "0");
+        assert_eq!(format_number(apply_discount(50.0, 0.0)), "50");
+    }
+
+    #[test]
+    fn test_repeated_operator_only_swaps() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.input_digit('5');
+        app.input_operator('+');
+        app.input_operator('+');
+        app.input_operator('+');
+        assert!(app.history.is_empty());
+        app.input_digit('3');
+        app.compute();
+        assert_eq!(app.display, "8");
+        assert_eq!(app.history.len(), 1);
+    }
+
+    #[test]
+    fn test_operator_swap_replaces_pending_operator() {
+        let mut app = CalcApp::new();
+        app.input_digit('6');
+        app.input_operator('+');
+        app.input_operator('*');
+        assert_eq!(app.operator, Some('*'));
+        assert_eq!(app.expression, "6 \u{00D7}");
+        app.input_digit('2');
+        app.compute();
+        assert_eq!(app.display, "12");
+    }
+
+    #[test]
+    fn test_display_font_size() {
+        assert_eq!(display_font_size(5), DISPLAY_FONT_LARGE);
+        assert_eq!(display_font_size(8), DISPLAY_FONT_LARGE);
+        assert_eq!(display_font_size(9), DISPLAY_FONT_MEDIUM);
+        assert_eq!(display_font_size(13), DISPLAY_FONT_SMALL);
+    }
+
+    #[test]
+    fn test_input_exponent() {
+        let mut app = CalcApp::new();
+        app.input_digit('1');
+        app.input_exponent();
+        app.input_digit('5');
+        assert_eq!(app.display, "1e5");
+        app.input_dot();
+        app.input_exponent();
+        assert_eq!(app.display, "1e5");
+
+        app.input_operator('+');
+        app.input_digit('1');
+        app.compute();
+        assert_eq!(app.display, "100001");
+    }
+
+    #[test]
+    fn test_toggle_sign_flips_exponent() {
+        let mut app = CalcApp::new();
+        app.input_digit('1');
+        app.input_exponent();
+        app.input_digit('5');
+        app.toggle_sign();
+        assert_eq!(app.display, "1e-5");
+        assert_eq!(app.current_value(), Some(1e-5));
+        app.toggle_sign();
+        assert_eq!(app.display, "1e5");
+
+        // Sign can be set before the exponent digits are typed
+        let mut app = CalcApp::new();
+        app.input_digit('2');
+        app.input_exponent();
+        app.toggle_sign();
+        app.input_digit('3');
+        assert_eq!(app.display, "2e-3");
+    }
+
+    #[test]
+    fn test_truncated_expression() {
+        let mut app = CalcApp::new();
+        app.expression = "5 + 3 =".to_string();
+        assert_eq!(app.truncated_expression(20), "5 + 3 =");
+
+        app.expression = "123456789".to_string();
+        assert_eq!(app.truncated_expression(7), "123\u{2026}789");
+        assert_eq!(app.truncated_expression(6), "123\u{2026}89");
+        assert_eq!(app.truncated_expression(7).chars().count(), 7);
+        assert_eq!(app.expression, "123456789");
+    }
+
+    #[test]
+    fn test_paste_expression() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.paste_expression("(2+3)*4");
+        assert_eq!(app.display, "20");
+        assert_eq!(app.expression, "(2+3)*4 =");
+        assert_eq!(app.history[0].expression, "(2+3)*4");
+        assert_eq!(app.history[0].result, "20");
+
+        app.input_operator('+');
+        app.input_digit('1');
+        app.compute();
+        assert_eq!(app.display, "21");
+    }
+
+    #[test]
+    fn test_paste_invalid_expression() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.input_digit('7');
+        app.input_operator('+');
+        app.paste_expression("2 + * 3");
+        assert_eq!(app.display, "Error");
+        assert_eq!(app.expression, "Invalid expression");
+        assert_eq!(app.operator, None);
+        assert!(app.history.is_empty());
+
+        app.input_digit('4');
+        assert_eq!(app.display, "4");
+    }
+
+    #[test]
+    fn test_percent_of() {
+        assert_eq!(percent_of(25.0, 200.0), Some(12.5));
+        assert_eq!(percent_of(-5.0, 20.0), Some(-25.0));
+        assert_eq!(percent_of(25.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_tip_breakdown() {
+        assert_eq!(tip_breakdown(50.0, 18.0), (9.0, 59.0));
+        let (tip, total) = tip_breakdown(23.45, 15.0);
+        assert_eq!(format_number(tip), "3.5175");
+        assert_eq!(format_number(total), "26.9675");
+    }
+
+    #[test]
+    fn test_sum_and_average_list() {
+        assert_eq!(sum_list("1,2,3"), Ok(6.0));
+        assert_eq!(average_list("1,2,3"), Ok(2.0));
+        assert_eq!(average_list(" 1.5 ,\t-2.5 , 4 "), Ok(1.0));
+        assert_eq!(sum_list(""), Ok(0.0));
+        assert_eq!(average_list(""), Err(CalcError::DivisionByZero));
+        assert_eq!(average_list("  "), Err(CalcError::DivisionByZero));
+        assert_eq!(sum_list("1,,2"), Err(CalcError::InvalidInput));
+        assert_eq!(sum_list("1,x"), Err(CalcError::InvalidInput));
+    }
+
+    #[test]
+    fn test_format_currency() {
+        assert_eq!(format_currency(1234.5, "$", 2), "$1,234.50");
+        assert_eq!(format_currency(-1234.5, "$", 2), "-$1,234.50");
+        assert_eq!(format_currency(1_000_000.0, "\u{20AC}", 0), "\u{20AC}1,000,000");
+        assert_eq!(format_currency(-0.001, "$", 2), "$0.00");
+    }
+
+    #[test]
+    fn test_percent_difference() {
+        assert!((percent_difference(40.0, 60.0).unwrap() - 40.0).abs() < 1e-12);
+        assert_eq!(percent_difference(60.0, 40.0), percent_difference(40.0, 60.0));
+        assert_eq!(percent_difference(0.0, 0.0), None);
+        assert_eq!(percent_difference(-5.0, 5.0), None);
+    }
+
+    #[test]
+    fn test_hours_to_hms() {
+        assert_eq!(hours_to_hms(1.5), (1, 30, 0.0));
+        assert_eq!(hms_to_hours(1, 30, 0.0), 1.5);
+        assert_eq!(hours_to_hms(0.1), (0, 6, 0.0));
+        assert_eq!(hours_to_hms(-1.5), (-1, -30, -0.0));
+        assert_eq!(hms_to_hours(-1, -30, -0.0), -1.5);
+        assert_eq!(format_hms(1.5), "1:30:00");
+        assert_eq!(format_hms(-0.75), "-0:45:00");
+        assert_eq!(format_hms(hms_to_hours(2, 5, 7.5)), "2:05:07.5");
+    }
+
+    #[test]
+    fn test_percent_remaining() {
+        assert_eq!(percent_remaining(25.0, 100.0), Some(75.0));
+        assert_eq!(percent_remaining(130.0, 100.0), Some(0.0));
+        assert_eq!(percent_remaining(0.0, 50.0), Some(100.0));
+        assert_eq!(percent_remaining(10.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_square_root_uses_precision() {
+        let mut app = CalcApp::new();
+        app.settings.precision = 4;
+        app.input_digit('2');
+        app.square_root();
+        assert_eq!(app.display, "1.4142");
+        assert_eq!(app.expression, "\u{221A}(2)");
+        assert!(app.just_computed);
+    }
+
+    #[test]
+    fn test_square_root_of_negative() {
+        let mut app = CalcApp::new();
+        app.input_digit('4');
+        app.toggle_sign();
+        app.square_root();
+        assert_eq!(app.display, "Error");
+        assert_eq!(app.expression, "Invalid input");
+    }
+
+    #[test]
+    fn test_square_and_reciprocal() {
+        let mut app = CalcApp::new();
+        app.input_digit('3');
+        app.square();
+        assert_eq!(app.display, "9");
+        assert_eq!(app.expression, "sqr(3)");
+
+        app.settings.precision = 3;
+        app.reciprocal();
+        assert_eq!(app.display, "0.111");
+        assert_eq!(app.expression, "1/(9)");
+
+        let mut app = CalcApp::new();
+        app.reciprocal();
+        assert_eq!(app.display, "Error");
+        assert_eq!(app.expression, "Cannot divide by zero");
+    }
+
+    #[test]
+    fn test_ans_refers_to_last_result() {
+        let mut app = CalcApp::new();
+        app.input_digit('5');
+        app.input_operator('+');
+        app.input_digit('3');
+        app.compute();
+        app.paste_expression("ans * 2");
+        assert_eq!(app.display, "16");
+        app.paste_expression("ANS + 1");
+        assert_eq!(app.display, "17");
+    }
+
+    #[test]
+    fn test_ans_without_previous_result_is_zero() {
+        let mut app = CalcApp::new();
+        app.paste_expression("ans + 4");
+        assert_eq!(app.display, "4");
+    }
+
+    #[test]
+    fn test_paste_number_range() {
+        let mut app = CalcApp::new();
+        app.paste_number("1e400");
+        assert_eq!(app.display, "Error");
+        assert_eq!(app.expression, "Overflow");
+
+        app.paste_number("1e308");
+        assert_eq!(app.display, "1e308");
+        assert_eq!(app.current_value(), Some(1e308));
+
+        app.paste_number(" +2.5E+3 ");
+        assert_eq!(app.display, "2.5e3");
+        app.paste_number("007");
+        assert_eq!(app.display, "7");
+        app.paste_number("abc");
+        assert_eq!(app.display, "Error");
+    }
+
+    #[test]
+    fn test_paste_number_as_second_operand() {
+        let mut app = CalcApp::new();
+        app.input_digit('4');
+        app.input_operator('*');
+        app.paste("2.5");
+        app.compute();
+        assert_eq!(app.display, "10");
+
+        app.paste("(1 + 2) * 3");
+        assert_eq!(app.display, "9");
+    }
+
+    #[test]
+    fn test_typed_exponent_overflow_is_error() {
+        let mut app = CalcApp::new();
+        app.input_digit('1');
+        app.input_exponent();
+        app.input_digit('3');
+        app.input_digit('0');
+        app.input_digit('8');
+        assert_eq!(app.display, "1e308");
+        app.backspace();
+        app.input_digit('9');
+        assert_eq!(app.display, "Error");
+        assert_eq!(app.expression, "Overflow");
+
+        app.input_digit('2');
+        assert_eq!(app.display, "2");
+    }
+
+    #[test]
+    fn test_custom_equals_suffix() {
+        let mut app = CalcApp::new();
+        app.settings.equals_suffix = " \u{21D2}".to_string();
+        app.input_digit('5');
+        app.input_operator('+');
+        app.input_digit('3');
+        app.compute();
+        assert_eq!(app.expression, "5 + 3 \u{21D2}");
+        assert_eq!(app.history.last().unwrap().expression, "5 + 3");
+
+        app.settings.equals_suffix = String::new();
+        app.paste_expression("2*3");
+        assert_eq!(app.expression, "2*3");
+    }
+
+    #[test]
+    fn test_log_accumulates_beyond_history() {
+        let path = std::env::temp_dir().join("calc_test_log.txt");
+        let _ = fs::remove_file(&path);
+        let mut app = CalcApp::new();
+        app.settings.log_path = Some(path.clone());
+        for _ in 0..DEFAULT_MAX_HISTORY + 2 {
+            app.input_digit('2');
+            app.input_operator('*');
+            app.input_digit('3');
+            app.compute();
+        }
+        let contents = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), DEFAULT_MAX_HISTORY + 2);
+        let fields: Vec<&str> = lines[0].split('\t').collect();
+        assert!(fields[0].parse::<u64>().is_ok());
+        assert_eq!(fields[1..], ["2 \u{00D7} 3", "6"]);
+    }
+
+    #[test]
+    fn test_log_failure_is_ignored() {
+        let mut app = CalcApp::new();
+        app.settings.log_path = Some(std::env::temp_dir().join("missing_dir/nested/calc.log"));
+        app.input_digit('1');
+        app.input_operator('+');
+        app.input_digit('1');
+        app.compute();
+        assert_eq!(app.display, "2");
+    }
+
+    #[test]
+    fn test_recompute_last_after_precision_change() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.settings.precision = 10;
+        app.input_digit('1');
+        app.input_operator('/');
+        app.input_digit('3');
+        app.compute();
+        assert_eq!(app.display, "0.3333333333");
+
+        app.settings.precision = 4;
+        app.recompute_last();
+        assert_eq!(app.display, "0.3333");
+        assert_eq!(app.expression, "1 \u{00F7} 3 =");
+        assert_eq!(app.history.len(), 1);
+    }
+
+    #[test]
+    fn test_recompute_last_after_raising_precision() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.settings.precision = 3;
+        app.paste_expression("1/3");
+        assert_eq!(app.history[0].result, "0.333");
+
+        app.settings.precision = 10;
+        app.recompute_last();
+        assert_eq!(app.display, "0.3333333333");
+        assert_eq!(app.last_result, Some(1.0 / 3.0));
+
+        app.paste_expression("2 * pi");
+        app.settings.precision = 3;
+        app.recompute_last();
+        assert_eq!(app.display, "6.283");
+    }
+
+    #[test]
+    fn test_recompute_last_keeps_ans_result() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.paste_expression("2 + 3");
+        app.paste_expression("ans * 2");
+        assert_eq!(app.display, "10");
+        app.recompute_last();
+        assert_eq!(app.display, "10");
+        assert_eq!(app.last_result, Some(10.0));
+    }
+
+    #[test]
+    fn test_recompute_last_button_entries() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.input_digit('9');
+        app.integer_sqrt();
+        app.input_digit('7');
+        app.recompute_last();
+        assert_eq!(app.display, "3");
+        assert_eq!(app.expression, "isqrt(9) =");
+    }
+
+    #[test]
+    fn test_recompute_last_without_history() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.input_digit('7');
+        app.recompute_last();
+        assert_eq!(app.display, "7");
+    }
+
+    #[test]
+    fn test_grouped_display_while_typing() {
+        let mut app = CalcApp::new();
+        for d in "1234567".chars() {
+            app.input_digit(d);
+        }
+        assert_eq!(app.display, "1234567");
+        assert_eq!(app.grouped_display(), "1,234,567");
+
+        app.input_dot();
+        app.input_digit('8');
+        app.input_digit('9');
+        app.input_digit('0');
+        app.input_digit('1');
+        assert_eq!(app.grouped_display(), "1,234,567.8901");
+    }
+
+    #[test]
+    fn test_group_digits() {
+        assert_eq!(group_digits("123", ','), "123");
+        assert_eq!(group_digits("1000", ','), "1,000");
+        assert_eq!(group_digits("-123456", ','), "-123,456");
+        assert_eq!(group_digits("-0", ','), "-0");
+        assert_eq!(group_digits("12345e10", ','), "12,345e10");
+        assert_eq!(group_digits("Error", ','), "Error");
+    }
+
+    #[test]
+    fn test_min_max_operators() {
+        let mut app = CalcApp::new();
+        app.input_digit('3');
+        app.input_operator(OP_MIN);
+        assert_eq!(app.expression, "3 min");
+        app.input_digit('7');
+        app.compute();
+        assert_eq!(app.display, "3");
+        assert_eq!(app.history.last().unwrap().to_string(), "min(3, 7) = 3");
+
+        app.input_digit('3');
+        app.input_operator(OP_MAX);
+        app.input_digit('7');
+        app.compute();
+        assert_eq!(app.display, "7");
+        assert_eq!(app.history.last().unwrap().to_string(), "max(3, 7) = 7");
+        assert_eq!(apply_op(-2.0, OP_MAX, -5.0), Ok(-2.0));
+    }
+
+    #[test]
+    fn test_type_sequence() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "12.5 \u{00D7} 4 =");
+        assert_eq!(app.display, "50");
+        assert_eq!(app.history.last().unwrap().to_string(), "12.5 \u{00D7} 4 = 50");
+
+        type_sequence(&mut app, "C 200 + 10 %");
+        assert_eq!(app.display, "20");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "220");
+    }
+
+    #[test]
+    fn test_unary_on_pending_operation() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        type_sequence(&mut app, "9+");
+        app.square_root();
+        assert_eq!(app.display, "3");
+        assert_eq!(app.expression, "9 + \u{221A}(9)");
+        assert!(app.history.is_empty());
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "12");
+        assert_eq!(app.history.last().unwrap().to_string(), "9 + 3 = 12");
+
+        // Typing after the function replaces its result
+        type_sequence(&mut app, "C 2*4");
+        app.reciprocal();
+        assert_eq!(app.display, "0.25");
+        type_sequence(&mut app, "5=");
+        assert_eq!(app.display, "10");
+
+        type_sequence(&mut app, "C 1-4");
+        app.square_root();
+        assert_eq!(app.display, "2");
+        app.square();
+        assert_eq!(app.expression, "1 - sqr(2)");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "-3");
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        assert_eq!(round_with(2.5, 0, RoundingMode::HalfUp), 3.0);
+        assert_eq!(round_with(2.5, 0, RoundingMode::HalfEven), 2.0);
+        assert_eq!(round_with(3.5, 0, RoundingMode::HalfEven), 4.0);
+        assert_eq!(round_with(-2.5, 0, RoundingMode::HalfUp), -3.0);
+        assert_eq!(round_with(2.7, 0, RoundingMode::Down), 2.0);
+        assert_eq!(round_with(-2.2, 0, RoundingMode::Up), -3.0);
+        assert_eq!(round_with(1.25, 1, RoundingMode::HalfUp), 1.3);
+        assert_eq!(format_rounded(1.0 / 3.0, 2, RoundingMode::Up), "0.34");
+
+        let mut app = CalcApp::new();
+        app.settings.precision = 0;
+        app.settings.rounding = RoundingMode::HalfUp;
+        type_sequence(&mut app, "5/2=");
+        assert_eq!(app.display, "3");
+        app.settings.rounding = RoundingMode::HalfEven;
+        type_sequence(&mut app, "5/2=");
+        assert_eq!(app.display, "2");
+    }
+
+    #[test]
+    fn test_trim_history_to() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        for i in 0..10 {
+            app.history.push(HistoryEntry::new(format!("{} + 0", i), i.to_string()));
+        }
+        app.trim_history_to(3);
+        let results: Vec<&str> = app.history.iter().map(|e| e.result.as_str()).collect();
+        assert_eq!(results, ["7", "8", "9"]);
+        app.trim_history_to(5);
+        assert_eq!(app.history.len(), 3);
+        app.trim_history_to(0);
+        assert!(app.history.is_empty());
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "12+");
+        let mut branch = app.clone();
+        type_sequence(&mut branch, "30");
+        branch.memory[0] = 5.0;
+        branch.settings.precision = 2;
+
+        assert_eq!(branch.display, "30");
+        assert_eq!(app.display, "12");
+        assert_eq!(app.first_operand, Some(12.0));
+        assert!(app.waiting_for_second);
+        assert_ne!(app.memory[0], 5.0);
+        assert_eq!(app.settings.precision, DEFAULT_PRECISION);
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        type_sequence(&mut app, "10/4=*");
+        app.memory = [1.5, 0.0, -2.0, 1e20];
+        app.settings.precision = 3;
+        app.settings.rounding = RoundingMode::Up;
+        app.settings.log_path = Some(PathBuf::from("calc.log"));
+        app.blaze_it = true;
+        app.blaze_start = Some(std::time::Instant::now());
+
+        let restored = CalcApp::from_session_string(&app.to_session_string()).unwrap();
+        assert_eq!(restored.display, "2.5");
+        assert_eq!(restored.expression, "2.5 \u{00D7}");
+        assert_eq!(restored.first_operand, Some(2.5));
+        assert_eq!(restored.operator, Some('*'));
+        assert!(restored.waiting_for_second);
+        assert_eq!(restored.last_result, Some(2.5));
+        assert_eq!(restored.history, app.history);
+        assert_eq!(restored.memory, app.memory);
+        assert_eq!(restored.settings, app.settings);
+        assert!(!restored.blaze_it);
+        assert!(restored.blaze_start.is_none());
+
+        assert!(CalcApp::from_session_string("not a session").is_err());
+    }
+
+    #[test]
+    fn test_backspace_cancels_pending_operator() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "5+");
+        app.backspace();
+        assert_eq!(app.display, "5");
+        assert_eq!(app.operator, None);
+        assert_eq!(app.first_operand, None);
+        assert_eq!(app.expression, "");
+        type_sequence(&mut app, "5");
+        assert_eq!(app.display, "55");
+        type_sequence(&mut app, "-5=");
+        assert_eq!(app.display, "50");
+    }
+
+    #[test]
+    fn test_increase_decrease_by_percent() {
+        assert_eq!(increase_by_percent(100.0, 10.0), 110.0);
+        assert_eq!(decrease_by_percent(100.0, 10.0), 90.0);
+        assert_eq!(decrease_by_percent(100.0, 150.0), -50.0);
+
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "100");
+        app.input_operator(OP_PERCENT_UP);
+        type_sequence(&mut app, "10=");
+        assert_eq!(app.display, "110");
+        assert_eq!(app.history.last().unwrap().to_string(), "100 +% 10 = 110");
+
+        // Repeated markdown keeps applying to the running value
+        app.input_operator(OP_PERCENT_DOWN);
+        type_sequence(&mut app, "10=");
+        assert_eq!(app.display, "99");
+    }
+
+    #[test]
+    fn test_history_panel_width() {
+        assert_eq!(history_panel_width(&[], 230.0, 480.0, 10.0), 230.0);
+
+        let entry = |expression: &str, result: &str| HistoryEntry::new(expression, result);
+        let history = vec![entry("1 + 1", "2"), entry("123456789 \u{00D7} 987654321 + 1", "121932631112635270")];
+        assert_eq!(history_panel_width(&history, 230.0, 480.0, 10.0), 250.0);
+
+        let long = vec![entry(&"9".repeat(200), "1")];
+        assert_eq!(history_panel_width(&long, 230.0, 480.0, 10.0), 480.0);
+    }
+
+    #[test]
+    fn test_history_path_from_env() {
+        let path = std::env::temp_dir().join("calc_test_env_history.txt");
+        let history = vec![HistoryEntry::new("6 \u{00D7} 7", "42")];
+        let resolved = history_path_from(Some(path.clone().into_os_string()));
+        assert_eq!(resolved, path);
+        save_history_to(&resolved, &history);
+        assert_eq!(load_history_from(&path), history);
+        let _ = fs::remove_file(&path);
+
+        // Unset or empty falls back to the file next to the executable
+        let default = history_path_from(None);
+        assert_eq!(default.file_name().unwrap(), "calc_history.txt");
+        assert_eq!(history_path_from(Some(OsString::new())), default);
+    }
+
+    #[test]
+    fn test_swap_operands() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "10-3");
+        app.swap_operands();
+        assert_eq!(app.expression, "3 -");
+        assert_eq!(app.display, "10");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "-7");
+
+        // Only the first operand: nothing to swap
+        type_sequence(&mut app, "C 8/");
+        app.swap_operands();
+        assert_eq!(app.first_operand, Some(8.0));
+        assert!(app.waiting_for_second);
+        type_sequence(&mut app, "2");
+        app.swap_operands();
+        type_sequence(&mut app, "4=");
+        assert_eq!(app.display, "0.5");
+    }
+
+    #[test]
+    fn test_rational_mode() {
+        let mut app = CalcApp::new();
+        app.settings.rational_mode = true;
+        app.paste_expression("1/3 + 1/3");
+        assert_eq!(app.display, "2/3");
+        assert_eq!(app.history.last().unwrap().to_string(), "1/3 + 1/3 = 2/3");
+        app.paste_expression("1/2 * 2/3");
+        assert_eq!(app.display, "1/3");
+
+        // Keypad: the fraction carries into the next operation exactly
+        type_sequence(&mut app, "C 1/3=");
+        assert_eq!(app.display, "1/3");
+        type_sequence(&mut app, "*3=");
+        assert_eq!(app.display, "1");
+        assert_eq!(app.history.last().unwrap().to_string(), "1/3 \u{00D7} 3 = 1");
+
+        // Non-integer operands fall back to floating point
+        app.paste_expression("sqrt(4) / 3");
+        assert_eq!(app.display, "0.6666666667");
+        type_sequence(&mut app, "0.5+0.25=");
+        assert_eq!(app.display, "0.75");
+
+        app.settings.rational_mode = false;
+        app.paste_expression("1/3 + 1/3");
+        assert_eq!(app.display, "0.6666666667");
+    }
+
+    #[test]
+    fn test_percent_after_equals_records_history() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "150+50=%");
+        assert_eq!(app.display, "2");
+        assert_eq!(app.expression, "200% =");
+        assert!(app.just_computed);
+        assert_eq!(app.history.last().unwrap().to_string(), "200% = 2");
+
+        // Still a no-op when pressed twice
+        type_sequence(&mut app, "%");
+        assert_eq!(app.display, "2");
+        assert_eq!(app.history.last().unwrap().to_string(), "200% = 2");
+    }
+
+    #[test]
+    fn test_hex_input() {
+        let mut app = CalcApp::new();
+        app.input_hex_digit('F');
+        assert_eq!(app.display, "0", "hex digits need hex mode");
+
+        app.set_base(Base::Hex);
+        app.input_hex_digit('F');
+        app.input_hex_digit('f');
+        app.input_hex_digit('G');
+        app.input_dot();
+        assert_eq!(app.display, "FF");
+        app.set_base(Base::Decimal);
+        assert_eq!(app.display, "255");
+
+        app.set_base(Base::Hex);
+        assert_eq!(app.display, "FF");
+        app.input_operator('+');
+        app.input_hex_digit('1');
+        app.compute();
+        assert_eq!(app.display, "100");
+        app.set_base(Base::Decimal);
+        assert_eq!(app.display, "256");
+        assert_eq!(format_hex(-255.9), "-FF");
+    }
+
+    #[test]
+    fn test_error_status() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "6/0=");
+        assert!(app.is_error());
+        assert_eq!(app.last_error(), Some(CalcError::DivisionByZero));
+
+        type_sequence(&mut app, "6/3=");
+        assert!(!app.is_error());
+        assert_eq!(app.last_error(), None);
+        assert_eq!(app.display, "2");
+
+        app.paste_expression("2 +");
+        assert_eq!(app.last_error(), Some(CalcError::Syntax));
+        app.paste_expression("2 + 2");
+        assert!(!app.is_error());
+
+        type_sequence(&mut app, "C 0");
+        app.reciprocal();
+        assert!(app.is_error());
+        app.clear_entry();
+        assert!(!app.is_error());
+    }
+
+    #[test]
+    fn test_insert_constant() {
+        let mut app = CalcApp::new();
+        app.insert_constant(Constant::Phi);
+        assert!(app.display.starts_with("1.618"));
+        app.insert_constant(Constant::Sqrt2);
+        assert!(app.display.starts_with("1.414"));
+
+        // As the second operand of a pending operation
+        type_sequence(&mut app, "C 2*");
+        app.insert_constant(Constant::Pi);
+        type_sequence(&mut app, "=");
+        assert!(app.display.starts_with("6.283"));
+        assert!(Constant::ALL.iter().all(|c| c.value().is_finite()));
+    }
+
+    #[test]
+    fn test_history_not_persisted_when_disabled() {
+        let mut app = CalcApp::new();
+        app.settings.persist_history = false;
+        let marker = "8675309 + 0";
+        app.add_history(marker.to_string(), "8675309".to_string());
+        app.trim_history_to(5);
+        assert_eq!(app.history.last().unwrap().expression, marker);
+        let on_disk = fs::read_to_string(history_path()).unwrap_or_default();
+        assert!(!on_disk.contains(marker));
+    }
+
+    #[test]
+    fn test_memory_swap() {
+        let mut app = CalcApp::new();
+        app.memory[1] = 5.0;
+        type_sequence(&mut app, "9");
+        app.memory_swap(1);
+        assert_eq!(app.display, "5");
+        assert_eq!(app.memory[1], 9.0);
+
+        type_sequence(&mut app, "1/0=");
+        app.memory_swap(1);
+        assert!(app.is_error());
+        assert_eq!(app.memory[1], 9.0);
+    }
+
+    #[test]
+    fn test_current_op_symbol() {
+        let mut app = CalcApp::new();
+        assert_eq!(app.current_op_symbol(), None);
+        type_sequence(&mut app, "6/");
+        assert_eq!(app.current_op_symbol(), Some("\u{00F7}"));
+        type_sequence(&mut app, "*");
+        assert_eq!(app.current_op_symbol(), Some("\u{00D7}"));
+        type_sequence(&mut app, "2=");
+        assert_eq!(app.current_op_symbol(), None);
+    }
+
+    #[test]
+    fn test_round_to_multiple() {
+        assert_eq!(round_to_multiple(47.0, 5.0), Some(45.0));
+        assert_eq!(round_to_multiple(48.0, 5.0), Some(50.0));
+        assert_eq!(round_to_multiple(-47.0, 5.0), Some(-45.0));
+        assert_eq!(round_to_multiple(47.0, 0.0), None);
+
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "48");
+        app.input_operator(OP_ROUND_TO);
+        type_sequence(&mut app, "5=");
+        assert_eq!(app.display, "50");
+        assert_eq!(app.history.last().unwrap().to_string(), "round(48, 5) = 50");
+        app.recompute_last();
+        assert_eq!(app.display, "50");
+
+        type_sequence(&mut app, "7");
+        app.input_operator(OP_ROUND_TO);
+        type_sequence(&mut app, "0=");
+        assert_eq!(app.last_error(), Some(CalcError::InvalidInput));
+    }
+
+    #[test]
+    fn test_save_history_creates_parent_dirs() {
+        let root = std::env::temp_dir().join("calc_test_nested_history");
+        let _ = fs::remove_dir_all(&root);
+        let path = root.join("a").join("b").join("history.txt");
+        let history = vec![HistoryEntry::new("1 + 1", "2")];
+        save_history_to(&path, &history);
+        assert!(path.exists());
+        assert_eq!(load_history_from(&path), history);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_exp_and_pow10() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "0");
+        app.exp();
+        assert_eq!(app.display, "1");
+        assert_eq!(app.history.last().unwrap().to_string(), "e^0 = 1");
+
+        type_sequence(&mut app, "3");
+        app.pow10();
+        assert_eq!(app.display, "1000");
+        assert_eq!(app.history.last().unwrap().to_string(), "10^3 = 1000");
+
+        type_sequence(&mut app, "2");
+        app.exp();
+        app.recompute_last();
+        assert_eq!(app.display, "7.3890560989");
+
+        type_sequence(&mut app, "1000");
+        app.exp();
+        assert_eq!(app.last_error(), Some(CalcError::Overflow));
+    }
+
+    #[test]
+    fn test_is_result() {
+        let mut app = CalcApp::new();
+        assert!(!app.is_result());
+        type_sequence(&mut app, "2+3");
+        assert!(!app.is_result());
+        type_sequence(&mut app, "=");
+        assert!(app.is_result());
+        type_sequence(&mut app, "7");
+        assert!(!app.is_result());
+
+        type_sequence(&mut app, "=");
+        app.square_root();
+        assert!(app.is_result());
+        app.clear_entry();
+        assert!(!app.is_result());
+        type_sequence(&mut app, "1/0=");
+        assert!(!app.is_result());
+        app.input_dot();
+        assert!(!app.is_result());
+    }
+
+    #[test]
+    fn test_set_layout() {
+        let mut app = CalcApp::new();
+        app.set_layout(Layout::Programmer);
+        app.set_base(Base::Hex);
+        app.input_hex_digit('A');
+        app.set_layout(Layout::Basic);
+        assert_eq!(app.settings.layout, Layout::Basic);
+        assert_eq!(app.base, Base::Decimal);
+        assert_eq!(app.display, "10");
+        for layout in Layout::ALL {
+            assert_eq!(Layout::from_name(layout.name()), Some(layout));
+        }
+    }
+
+    #[test]
+    fn test_macro_replay() {
+        let mut app = CalcApp::new();
+        app.record_macro();
+        type_sequence(&mut app, "7+5*2=");
+        app.stop_macro();
+        assert_eq!(app.display, "24");
+        assert_eq!(app.macro_steps, vec![('+', 5.0), ('*', 2.0)]);
+
+        type_sequence(&mut app, "10");
+        app.replay_macro();
+        assert_eq!(app.display, "30");
+        assert_eq!(app.history.last().unwrap().to_string(), "(10 + 5) \u{00D7} 2 = 30");
+        app.recompute_last();
+        assert_eq!(app.display, "30");
+
+        // Nothing is recorded once stopped
+        type_sequence(&mut app, "1-1=");
+        assert_eq!(app.macro_steps.len(), 2);
+    }
+
+    #[test]
+    fn test_equals_without_pending_operation() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.compute();
+        assert_eq!(app.display, "0");
+        assert!(app.history.is_empty());
+        assert!(!app.just_computed);
+
+        type_sequence(&mut app, "5==");
+        assert_eq!(app.display, "5");
+        assert_eq!(app.expression, "");
+        assert!(app.history.is_empty());
+        type_sequence(&mut app, "3");
+        assert_eq!(app.display, "53");
+    }
+
+    #[test]
+    fn test_history_autosave_off() {
+        let mut app = CalcApp::new();
+        app.settings.autosave = false;
+        let marker = "5551212 + 0";
+        app.add_history(marker.to_string(), "5551212".to_string());
+        let on_disk = fs::read_to_string(history_path()).unwrap_or_default();
+        assert!(!on_disk.contains(marker));
+
+        let path = std::env::temp_dir().join("calc_test_flush_history.txt");
+        app.flush_history_to(&path);
+        assert_eq!(load_history_from(&path), app.history);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_percent_of_recalled_memory() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.memory[0] = 200.0;
+        type_sequence(&mut app, "15*");
+        app.memory_recall(0);
+        app.percent();
+        assert_eq!(app.display, "30");
+        assert_eq!(app.history[0].expression, "15% of 200");
+        assert_eq!(app.operator, None);
+
+        // Replacing the recalled value gives the usual percent
+        type_sequence(&mut app, "C50+");
+        app.memory_recall(0);
+        app.clear_entry();
+        type_sequence(&mut app, "10%=");
+        assert_eq!(app.display, "55");
+    }
+
+    #[test]
+    fn test_negative_operand_parenthesized() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.settings.persist_history = false;
+        type_sequence(&mut app, "5+3");
+        app.toggle_sign();
+        app.compute();
+        assert_eq!(app.expression, "5 + (-3) =");
+        assert_eq!(app.history[0].expression, "5 + (-3)");
+        assert_eq!(app.format_operand_for_expr(-3.0), "(-3)");
+        assert_eq!(app.format_operand_for_expr(3.0), "3");
+        assert_eq!(CalcApp::binary_expression("5", OP_MAX, "-3"), "max(5, -3)");
+        type_sequence(&mut app, "5");
+        app.input_operator(OP_MAX);
+        type_sequence(&mut app, "3");
+        app.toggle_sign();
+        app.compute();
+        assert_eq!(app.history[1].expression, "max(5, -3)");
+    }
+
+    #[test]
+    fn test_current_value() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "12.5");
+        assert_eq!(app.current_value(), Some(12.5));
+        type_sequence(&mut app, "/0=");
+        assert_eq!(app.display, "Error");
+        assert_eq!(app.current_value(), None);
+    }
+
+    #[test]
+    fn test_scientific_results_use_precision() {
+        let mut app = CalcApp::new();
+        app.settings.precision = 5;
+        type_sequence(&mut app, "2");
+        app.ln();
+        assert_eq!(app.display, "0.69315");
+        assert_eq!(app.expression, "ln(2)");
+        type_sequence(&mut app, "2");
+        app.square_root();
+        assert_eq!(app.display, "1.41421");
+        type_sequence(&mut app, "2");
+        app.log10();
+        assert_eq!(app.display, "0.30103");
+        type_sequence(&mut app, "0");
+        app.ln();
+        assert_eq!(app.last_error(), Some(CalcError::InvalidInput));
+    }
+
+    #[test]
+    fn test_inverse_modifier() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "0.5");
+        app.toggle_inverse();
+        app.sin();
+        assert_eq!(app.expression, "asin(0.5)");
+        assert_eq!(app.display, app.format(0.5f64.asin()));
+        assert!(!app.inverse_pending);
+
+        // The flag only applies once
+        type_sequence(&mut app, "0");
+        app.sin();
+        assert_eq!(app.expression, "sin(0)");
+
+        type_sequence(&mut app, "2");
+        app.toggle_inverse();
+        app.ln();
+        assert_eq!(app.expression, "e^2");
+
+        type_sequence(&mut app, "2");
+        app.toggle_inverse();
+        app.cos();
+        assert_eq!(app.last_error(), Some(CalcError::InvalidInput));
+    }
+
+    #[test]
+    fn test_angle_conversion() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        type_sequence(&mut app, "180");
+        app.deg_to_rad();
+        assert_eq!(app.display, app.format(std::f64::consts::PI));
+        assert_eq!(app.history[0].expression, "rad(180\u{00B0})");
+
+        type_sequence(&mut app, &std::f64::consts::PI.to_string());
+        app.rad_to_deg();
+        assert_eq!(app.display, "180");
+    }
+
+    #[test]
+    fn test_reformat_history() {
+        let mut app = CalcApp::new();
+        app.settings.persist_history = false;
+        app.history = vec![
+            HistoryEntry::new("1 / 3", "0.3333333333"),
+            HistoryEntry::new("1 / 0", "Error"),
+            HistoryEntry::new("2 + 2", "4"),
+        ];
+        app.settings.precision = 3;
+        app.reformat_history();
+        let results: Vec<&str> = app.history.iter().map(|e| e.result.as_str()).collect();
+        assert_eq!(results, ["0.333", "Error", "4"]);
+    }
+
+    #[test]
+    fn test_equals_without_second_operand_repeats_first() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        type_sequence(&mut app, "5+=");
+        assert_eq!(app.display, "10");
+        assert_eq!(app.history[0].expression, "5 + 5");
+
+        type_sequence(&mut app, "C6*=");
+        assert_eq!(app.display, "36");
+    }
+
+    #[test]
+    fn test_paste_grouped_number() {
+        let mut app = CalcApp::new();
+        app.paste("1,234,567");
+        assert_eq!(app.current_value(), Some(1234567.0));
+
+        app.settings.group_sep = '.';
+        app.paste("1.234.567");
+        assert_eq!(app.current_value(), Some(1234567.0));
+        app.paste("1.234,5");
+        assert_eq!(app.current_value(), Some(1234.5));
+        assert_eq!(app.grouped_display(), "1.234,5");
+    }
+
+    #[test]
+    fn test_history_format_version() {
+        assert!(compatible_history_format(API_VERSION));
+        assert!(compatible_history_format("1.0"));
+        assert!(compatible_history_format("1.7"));
+        assert!(!compatible_history_format("2.0"));
+        assert!(!compatible_history_format("0.9"));
+        assert!(!compatible_history_format("one"));
+        assert!(!compatible_history_format(""));
+
+        let path = std::env::temp_dir().join("calc_test_history_version.txt");
+        let history = vec![HistoryEntry::new("1 + 1", "2")];
+        save_history_to(&path, &history);
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().next(), Some(format!("#version {}", API_VERSION).as_str()));
+        assert_eq!(load_history_from(&path), history);
+
+        // Files without a header predate versioning and still load
+        fs::write(&path, "2 + 2\t4").unwrap();
+        assert_eq!(load_history_from(&path).len(), 1);
+        fs::write(&path, "#version 2.0\n2 + 2\t4").unwrap();
+        assert!(load_history_from(&path).is_empty());
+        let _ = fs::remove_file(&path);
     }
 
-    pub fn backspace(&mut self) {
-        if self.display == "Error" || self.just_computed {
-            return;
-        }
-        if self.display.len() > 1 {
-            self.display.pop();
-        } else {
-            self.display = "0".to_string();
-        }
+    #[test]
+    fn test_algebraic_precedence() {
+        let mut app = CalcApp::new();
+        app.settings.algebraic = true;
+        app.history.clear();
+        type_sequence(&mut app, "2+3*");
+        assert_eq!(app.expression, "2 + 3 \u{00D7}");
+        type_sequence(&mut app, "4=");
+        assert_eq!(app.display, "14");
+        assert_eq!(app.history[0].expression, "2 + 3 \u{00D7} 4");
+
+        type_sequence(&mut app, "C2*3+4=");
+        assert_eq!(app.display, "10");
+        type_sequence(&mut app, "C2+3*4/2-1=");
+        assert_eq!(app.display, "7");
+
+        // Replacing the deferring operator gives up the deferral
+        type_sequence(&mut app, "C2+3*-1=");
+        assert_eq!(app.display, "4");
+
+        // Chaining left to right is still the default
+        app.settings.algebraic = false;
+        type_sequence(&mut app, "C2+3*4=");
+        assert_eq!(app.display, "20");
     }
 
-    pub fn toggle_sign(&mut self) {
-        if self.display == "Error" || self.display == "0" {
-            return;
-        }
-        if self.display.starts_with('-') {
-            self.display.remove(0);
-        } else {
-            self.display.insert(0, '-');
-        }
+    #[test]
+    fn test_algebraic_backspace_restores_deferred() {
+        let mut app = CalcApp::new();
+        app.settings.algebraic = true;
+        type_sequence(&mut app, "2+3*");
+        app.backspace();
+        assert_eq!(app.expression, "2 +");
+        assert_eq!(app.display, "3");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "5");
     }
 
-    pub fn percent(&mut self) {
-        if let Ok(val) = self.display.parse::<f64>() {
-            self.display = format_number(val / 100.0);
-        }
+    #[test]
+    fn test_macro_records_algebraic_chain() {
+        let mut app = CalcApp::new();
+        app.settings.algebraic = true;
+        app.settings.persist_history = false;
+        app.record_macro();
+        type_sequence(&mut app, "7+5*2=");
+        assert_eq!(app.display, "17");
+        type_sequence(&mut app, "2*3+4*5^2-1=");
+        assert_eq!(app.display, "105");
+        app.stop_macro();
+        assert_eq!(app.macro_steps, vec![('+', 10.0), ('*', 3.0), ('+', 100.0), ('-', 1.0)]);
+
+        type_sequence(&mut app, "C1");
+        app.replay_macro();
+        assert_eq!(app.display, "132");
     }
-}
 
-impl Default for CalcApp {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn test_memory_percent_drops_deferred() {
+        let mut app = CalcApp::new();
+        app.settings.algebraic = true;
+        app.settings.persist_history = false;
+        app.memory[0] = 200.0;
+        type_sequence(&mut app, "2+15*");
+        app.memory_recall(0);
+        app.percent();
+        assert_eq!(app.display, "30");
+        assert!(app.deferred.is_empty());
+        type_sequence(&mut app, "+1=");
+        assert_eq!(app.display, "31");
     }
-}
 
-pub fn format_number(n: f64) -> String {
-    if n.is_nan() || n.is_infinite() {
-        return "Error".to_string();
+    #[test]
+    fn test_expression_line_keeps_deferred_prefix() {
+        let mut app = CalcApp::new();
+        app.settings.algebraic = true;
+        app.settings.persist_history = false;
+        type_sequence(&mut app, "2+3*9");
+        app.square_root();
+        assert_eq!(app.expression, "2 + 3 \u{00D7} \u{221A}(9)");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "11");
+
+        type_sequence(&mut app, "C2+8/4");
+        app.swap_operands();
+        assert_eq!(app.expression, "2 + 4 \u{00F7}");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "2.5");
     }
-    if n == n.floor() && n.abs() < 1e15 {
-        format!("{}", n as i64)
-    } else {
-        let s = format!("{:.10}", n);
-        let s = s.trim_end_matches('0');
-        let s = s.trim_end_matches('.');
-        s.to_string()
+
+    #[test]
+    fn test_clear_expression() {
+        let mut app = CalcApp::new();
+        type_sequence(&mut app, "12+3");
+        app.clear_expression();
+        assert_eq!(app.expression, "");
+        assert_eq!(app.display, "3");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "15");
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_constant_mode() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.toggle_constant_mode();
+        type_sequence(&mut app, "2+5=");
+        assert_eq!(app.display, "7");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "12");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "17");
+        assert_eq!(app.history.last().unwrap().expression, "12 + 5");
+
+        // A new number takes the constant too
+        type_sequence(&mut app, "10=");
+        assert_eq!(app.display, "15");
+
+        // Without K mode `=` with nothing pending stays a no-op
+        app.toggle_constant_mode();
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "15");
+    }
 
     #[test]
-    fn test_input_digit() {
+    fn test_preview_compute() {
         let mut app = CalcApp::new();
-        assert_eq!(app.display, "0");
+        app.history.clear();
+        app.settings.persist_history = false;
+        type_sequence(&mut app, "7*6");
+        assert_eq!(app.preview_compute(), Ok(42.0));
+        assert_eq!(app.display, "6");
+        assert_eq!(app.operator, Some('*'));
+        assert!(app.history.is_empty());
 
-        app.input_digit('5');
-        assert_eq!(app.display, "5");
+        type_sequence(&mut app, "/0");
+        assert_eq!(app.preview_compute(), Err(CalcError::DivisionByZero));
+        assert!(!app.is_error());
 
-        app.input_digit('3');
-        assert_eq!(app.display, "53");
+        type_sequence(&mut app, "C5");
+        assert_eq!(app.preview_compute(), Ok(5.0));
+
+        // K mode: `=` would repeat "+ 5"
+        type_sequence(&mut app, "C2+5=");
+        app.constant_mode = true;
+        assert_eq!(app.preview_compute(), Ok(12.0));
+        app.compute();
+        assert_eq!(app.display, "12");
     }
 
     #[test]
-    fn test_input_digit_replaces_zero() {
+    fn test_format_engineering() {
+        assert_eq!(format_engineering(12345678.0, 5), "12.346e6");
+        assert_eq!(format_engineering(0.0001234, 5), "123.4e-6");
+        assert_eq!(format_engineering(-4500.0, 3), "-4.5e3");
+        assert_eq!(format_engineering(999999.0, 3), "1e6");
+        assert_eq!(format_engineering(42.0, 10), "42");
+        assert_eq!(format_engineering(0.0, 5), "0");
+        assert_eq!(format_scientific(12345678.0, 5), "1.2346e7");
+        assert_eq!(format_scientific(0.0001234, 5), "1.234e-4");
+    }
+
+    #[test]
+    fn test_engineering_notation_setting() {
         let mut app = CalcApp::new();
-        app.input_digit('7');
-        assert_eq!(app.display, "7");
+        app.settings.notation = Notation::Engineering;
+        app.settings.precision = 5;
+        type_sequence(&mut app, "12345*1000=");
+        assert_eq!(app.display, "12.345e6");
+        assert_eq!(app.current_value(), Some(12345000.0));
     }
 
     #[test]
-    fn test_input_dot() {
+    fn test_tiny_results_not_shown_as_zero() {
+        assert_eq!(format_rounded(1e-12, 10, RoundingMode::HalfEven), "1e-12");
+        assert_eq!(format_number_with(-1e-12, 10), "-1e-12");
+        assert_eq!(format_number_with(1e-300, 10), "1e-300");
+        assert_eq!(format_rounded(0.0, 10, RoundingMode::HalfEven), "0");
+        assert_eq!(format_rounded(-0.0, 10, RoundingMode::HalfEven), "0");
+
         let mut app = CalcApp::new();
-        app.input_dot();
-        assert_eq!(app.display, "0.");
+        type_sequence(&mut app, "0.000001*0.000001=");
+        assert_eq!(app.display, "1e-12");
+        assert_eq!(app.current_value(), Some(1e-12));
+    }
 
-        app.input_digit('5');
-        assert_eq!(app.display, "0.5");
+    #[test]
+    fn test_cancel_blaze() {
+        let mut app = CalcApp::new();
+        app.settings.easter_egg = true;
+        type_sequence(&mut app, "400+20=");
+        assert!(app.blaze_it);
+        app.blaze_sound_played = true;
+        app.cancel_blaze();
+        assert!(!app.blaze_it);
+        assert_eq!(app.blaze_start, None);
+        assert!(!app.blaze_sound_played);
+        assert_eq!(app.display, "420");
+    }
 
-        // Second dot should not be added
-        app.input_dot();
-        assert_eq!(app.display, "0.5");
+    #[test]
+    fn test_blaze_duration_setting() {
+        let path = std::env::temp_dir().join("calc_test_blaze_duration.txt");
+        fs::write(&path, "blaze_duration=5").unwrap();
+        assert_eq!(load_settings_from(&path).blaze_duration, 5.0);
+        fs::write(&path, "blaze_duration=-3").unwrap();
+        assert_eq!(load_settings_from(&path).blaze_duration, DEFAULT_BLAZE_DURATION);
+        let _ = fs::remove_file(&path);
     }
 
     #[test]
-    fn test_addition() {
+    fn test_chained_percentages_use_running_total() {
         let mut app = CalcApp::new();
-        app.input_digit('2');
-        app.input_operator('+');
-        assert_eq!(app.first_operand, Some(2.0));
-        assert_eq!(app.operator, Some('+'));
+        app.history.clear();
+        type_sequence(&mut app, "100+10%+");
+        assert_eq!(app.display, "110");
+        type_sequence(&mut app, "5%");
+        assert_eq!(app.display, "5.5");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "115.5");
+        assert_eq!(app.history.last().unwrap().expression, "110 + 5.5");
 
-        app.input_digit('3');
-        app.compute();
-        assert_eq!(app.display, "5");
+        type_sequence(&mut app, "C200-10%-10%=");
+        assert_eq!(app.display, "162");
     }
 
     #[test]
-    fn test_subtraction() {
+    fn test_display_is_integer() {
         let mut app = CalcApp::new();
-        app.input_digit('1');
-        app.input_digit('0');
-        app.input_operator('-');
+        type_sequence(&mut app, "5");
+        assert!(app.display_is_integer());
+        type_sequence(&mut app, ".5");
+        assert!(!app.display_is_integer());
+        type_sequence(&mut app, "/0=");
+        assert!(!app.display_is_integer());
+    }
 
-        app.input_digit('3');
-        app.compute();
-        assert_eq!(app.display, "7");
+    #[test]
+    fn test_supported_operators() {
+        let ops = supported_operators();
+        for basic in [('+', "+"), ('-', "-"), ('*', "\u{00D7}"), ('/', "\u{00F7}")] {
+            assert!(ops.contains(&basic), "missing {:?}", basic);
+        }
+        // Each listed operator is one `apply_op` understands
+        for &(op, symbol) in ops {
+            assert!(apply_op(6.0, op, 3.0).is_ok(), "{} rejected", op);
+            assert_eq!(CalcApp::op_symbol(op), symbol);
+        }
     }
 
     #[test]
-    fn test_multiplication() {
+    fn test_last_result_rounded() {
         let mut app = CalcApp::new();
-        app.input_digit('4');
-        app.input_operator('*');
+        type_sequence(&mut app, "1/3=");
+        assert!(app.last_result_rounded());
+        type_sequence(&mut app, "1/2=");
+        assert!(!app.last_result_rounded());
+        type_sequence(&mut app, "1/3=C");
+        assert!(!app.last_result_rounded());
+    }
 
-        app.input_digit('5');
-        app.compute();
-        assert_eq!(app.display, "20");
+    #[test]
+    fn test_to_latex() {
+        let entry = |expression: &str, result: &str| HistoryEntry::new(expression, result);
+        assert_eq!(to_latex(&entry("\u{221A}(2)", "1.4142")), "\\sqrt{2} = 1.4142");
+        assert_eq!(to_latex(&entry("1/(3)", "1/3")), "\\frac{1}{3} = \\frac{1}{3}");
+        assert_eq!(to_latex(&entry("sqr(-4)", "16")), "(-4)^{2} = 16");
+        assert_eq!(
+            to_latex(&entry("9 + \u{221A}(9)", "12")),
+            "9 + \\sqrt{9} = 12"
+        );
+        assert_eq!(to_latex(&entry("6 \u{00D7} 7", "42")), "6 \\times 7 = 42");
+        assert_eq!(to_latex(&entry("e^2", "7.3890560989")), "e^{2} = 7.3890560989");
+        assert_eq!(to_latex(&entry("asin(0.5)", "0.5235987756")), "\\arcsin(0.5) = 0.5235987756");
+        assert_eq!(to_latex(&entry("2 ^ 10", "1024")), "{2}^{10} = 1024");
+        assert_eq!(to_latex(&entry("1 + 2 ^ 3 ^ 2", "513")), "1 + {2}^{{3}^{2}} = 513");
+        assert_eq!(to_latex(&entry("2 ^ (-1)", "0.5")), "{2}^{(-1)} = 0.5");
     }
 
     #[test]
-    fn test_division() {
+    fn test_percentage_decimal_toggle() {
         let mut app = CalcApp::new();
-        app.input_digit('2');
-        app.input_digit('0');
-        app.input_operator('/');
+        app.history.clear();
+        type_sequence(&mut app, "0.25");
+        app.as_percentage();
+        assert_eq!(app.display, "25");
+        assert!(app.percentage_form);
+        app.as_decimal();
+        assert_eq!(app.display, "0.25");
+        assert!(!app.percentage_form);
+        assert!(app.history.is_empty());
 
-        app.input_digit('4');
-        app.compute();
-        assert_eq!(app.display, "5");
+        // A converted value is an operand like any other
+        type_sequence(&mut app, "+1=");
+        assert_eq!(app.display, "1.25");
+
+        // Including the percentage form: 0.5 shown as 50 computes as 50
+        type_sequence(&mut app, "C0.5");
+        app.as_percentage();
+        type_sequence(&mut app, "+1=");
+        assert_eq!(app.display, "51");
     }
 
     #[test]
-    fn test_division_by_zero() {
+    fn test_toggle_sign_partial_and_invalid_displays() {
         let mut app = CalcApp::new();
-        app.input_digit('1');
-        app.input_operator('/');
+        type_sequence(&mut app, "1");
+        app.input_exponent();
+        assert_eq!(app.display, "1e");
+        app.toggle_sign();
+        assert_eq!(app.display, "1e-");
+        app.toggle_sign();
+        assert_eq!(app.display, "1e");
 
-        app.input_digit('0');
-        app.compute();
+        type_sequence(&mut app, "C1/0=");
+        app.toggle_sign();
         assert_eq!(app.display, "Error");
-        assert_eq!(app.expression, "Cannot divide by zero");
+
+        // An "Error" display without a recorded error, e.g. from a session
+        app.error = None;
+        app.toggle_sign();
+        assert_eq!(app.display, "Error");
+        app.display = "ee".to_string();
+        app.toggle_sign();
+        assert_eq!(app.display, "ee");
     }
 
     #[test]
-    fn test_clear() {
+    fn test_toggle_sign_on_scientific_values() {
         let mut app = CalcApp::new();
-        app.input_digit('5');
-        app.input_operator('+');
-        app.clear();
+        app.history.clear();
+        app.settings.persist_history = false;
+        app.settings.notation = Notation::Scientific;
+        type_sequence(&mut app, "3*5=");
+        assert_eq!(app.display, "1.5e1");
+        app.toggle_sign();
+        assert_eq!(app.display, "-1.5e1");
+        type_sequence(&mut app, "+20=");
+        assert_eq!(app.display, "5e0");
 
-        assert_eq!(app.display, "0");
-        assert_eq!(app.expression, "");
-        assert_eq!(app.first_operand, None);
-        assert_eq!(app.operator, None);
+        // A recalled operand is a value, not an exponent being typed
+        app.memory[0] = 15.0;
+        type_sequence(&mut app, "C5+");
+        app.memory_recall(0);
+        assert_eq!(app.display, "1.5e1");
+        app.toggle_sign();
+        assert_eq!(app.display, "-1.5e1");
+        type_sequence(&mut app, "=");
+        assert_eq!(app.display, "-1e1");
+
+        // Tiny standard-notation results fall back to scientific form
+        app.settings.notation = Notation::Standard;
+        type_sequence(&mut app, "C1/1000000000000=");
+        assert_eq!(app.display, "1e-12");
+        app.toggle_sign();
+        assert_eq!(app.display, "-1e-12");
     }
 
     #[test]
-    fn test_clear_entry() {
+    fn test_import_history_csv() {
+        let path = std::env::temp_dir().join("calc_test_import.csv");
+        fs::write(
+            &path,
+            "expression,result\r\n\
+             1 + 2,3\r\n\
+             \"max(3, 7)\",7\r\n\
+             \"say \"\"hi\"\"\",0\r\n\
+             only one field\r\n\
+             bad\"quote,1\r\n\
+             \r\n\
+             2 \u{00D7} 3,6\r\n",
+        )
+        .unwrap();
         let mut app = CalcApp::new();
-        app.input_digit('5');
-        app.clear_entry();
-        assert_eq!(app.display, "0");
+        app.settings.persist_history = false;
+        let skipped = app.import_history_csv(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(skipped, 2);
+        let rows: Vec<(&str, &str)> =
+            app.history.iter().map(|e| (e.expression.as_str(), e.result.as_str())).collect();
+        assert_eq!(
+            rows,
+            [("1 + 2", "3"), ("max(3, 7)", "7"), ("say \"hi\"", "0"), ("2 \u{00D7} 3", "6")]
+        );
+        assert!(app.import_history_csv(&path).is_err());
     }
 
     #[test]
-    fn test_backspace() {
+    fn test_negate_result() {
         let mut app = CalcApp::new();
-        app.input_digit('5');
-        app.input_digit('3');
-        app.backspace();
-        assert_eq!(app.display, "5");
+        type_sequence(&mut app, "5+3=");
+        app.negate_result();
+        assert_eq!(app.display, "-8");
+        assert_eq!(app.last_result, Some(-8.0));
+        type_sequence(&mut app, "+2=");
+        assert_eq!(app.display, "-6");
 
-        app.backspace();
-        assert_eq!(app.display, "0");
+        app.settings.notation = Notation::Scientific;
+        app.settings.precision = 3;
+        type_sequence(&mut app, "12000*1000=");
+        assert_eq!(app.display, "1.2e7");
+        app.negate_result();
+        assert_eq!(app.display, "-1.2e7");
+
+        // Negating a recalled value leaves `ans` alone
+        app.settings.notation = Notation::Standard;
+        app.memory[0] = 9.0;
+        type_sequence(&mut app, "C2+3=");
+        app.memory_recall(0);
+        app.negate_result();
+        assert_eq!(app.display, "-9");
+        assert_eq!(app.last_result, Some(5.0));
     }
 
     #[test]
-    fn test_toggle_sign_positive() {
+    fn test_zeros_after_decimal_point_kept() {
         let mut app = CalcApp::new();
-        app.input_digit('5');
-        app.toggle_sign();
-        assert_eq!(app.display, "-5");
+        type_sequence(&mut app, ".005");
+        assert_eq!(app.display, "0.005");
+        assert_eq!(app.current_value(), Some(0.005));
+
+        type_sequence(&mut app, "C0.000");
+        assert_eq!(app.display, "0.000");
+        app.input_double_zero();
+        assert_eq!(app.display, "0.00000");
+        type_sequence(&mut app, "1+1=");
+        assert_eq!(app.display, "1.000001");
     }
 
     #[test]
-    fn test_toggle_sign_negative() {
+    fn test_default_max_history() {
+        assert_eq!(Settings::default().max_history, DEFAULT_MAX_HISTORY);
+        let app = CalcApp::new();
+        assert_eq!(app.settings.max_history, DEFAULT_MAX_HISTORY);
+    }
+
+    #[test]
+    fn test_restore_cleared_history() {
         let mut app = CalcApp::new();
-        app.input_digit('5');
-        app.toggle_sign();
-        app.toggle_sign();
-        assert_eq!(app.display, "5");
+        app.settings.persist_history = false;
+        app.history.clear();
+        type_sequence(&mut app, "1+1=2+2=");
+        let before = app.history.clone();
+        app.clear_history();
+        assert!(app.history.is_empty());
+
+        // Clearing an already empty history keeps the copy
+        app.clear_history();
+        app.restore_cleared_history();
+        assert_eq!(app.history, before);
+
+        // Only one clear can be undone
+        app.restore_cleared_history();
+        assert_eq!(app.history, before);
     }
 
     #[test]
-    fn test_toggle_sign_on_zero_does_nothing() {
+    fn test_unary_result_chains_into_power() {
         let mut app = CalcApp::new();
-        app.toggle_sign();
-        assert_eq!(app.display, "0");
+        type_sequence(&mut app, "3");
+        app.square();
+        assert!(app.is_result());
+        assert_eq!(app.last_result, Some(9.0));
+        type_sequence(&mut app, "^2=");
+        assert_eq!(app.display, "81");
+        assert_eq!(app.history.last().unwrap().expression, "9 ^ 2");
+
+        // Every unary function leaves a result an operator can continue from
+        let unary: [fn(&mut CalcApp); 5] =
+            [CalcApp::square_root, CalcApp::reciprocal, CalcApp::sin, CalcApp::ln, CalcApp::exp];
+        for f in unary {
+            type_sequence(&mut app, "C4");
+            f(&mut app);
+            let shown = app.display.clone();
+            assert!(app.is_result());
+            type_sequence(&mut app, "+1=");
+            assert_eq!(app.history.last().unwrap().expression, format!("{} + 1", shown));
+        }
+
+        assert_eq!(apply_op(-8.0, '^', 0.5), Err(CalcError::InvalidInput));
+        assert_eq!(apply_op(10.0, '^', 400.0), Err(CalcError::Overflow));
     }
 
     #[test]
-    fn test_percent() {
+    fn test_algebraic_power_chains() {
         let mut app = CalcApp::new();
-        app.input_digit('5');
-        app.input_digit('0');
-        app.percent();
+        app.settings.algebraic = true;
+        app.settings.persist_history = false;
+        for (keys, expected) in [
+            ("8/2^2/2=", "1"),
+            ("8/2^2*2=", "4"),
+            ("2+3*2^2=", "14"),
+            ("2*3^2+1=", "19"),
+            ("2^3*2=", "16"),
+            ("2^3^2=", "512"),
+            ("64/2^3^2*2=", "0.25"),
+        ] {
+            type_sequence(&mut app, "C");
+            type_sequence(&mut app, keys);
+            assert_eq!(app.display, expected, "{}", keys);
+            let expr = keys.trim_end_matches('=');
+            assert_eq!(evaluate(expr).map(format_number), Ok(expected.to_string()), "{}", keys);
+        }
+
+        // Chain mode is left to right, except that powers group the same way
+        app.settings.algebraic = false;
+        type_sequence(&mut app, "C2^3^2=");
+        assert_eq!(app.display, "512");
+        type_sequence(&mut app, "C2^3*2=");
+        assert_eq!(app.display, "16");
+        type_sequence(&mut app, "C2^3^+1=");
+        assert_eq!(app.display, "9");
+    }
+
+    #[test]
+    fn test_standalone_percent_history_entry() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        type_sequence(&mut app, "50%");
         assert_eq!(app.display, "0.5");
+        assert_eq!(app.history.len(), 1);
+        assert_eq!(app.history[0].expression, "50%");
+        assert_eq!(app.history[0].to_string(), "50% = 0.5");
     }
 
     #[test]
-    fn test_format_number_integer() {
-        assert_eq!(format_number(42.0), "42");
-        assert_eq!(format_number(-15.0), "-15");
+    fn test_store_variable_records_assignment() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.settings.persist_history = false;
+        type_sequence(&mut app, "2+3=");
+        assert_eq!(app.store_variable("x"), Ok(()));
+        assert_eq!(app.variables.get("x"), Some(&5.0));
+        assert_eq!(app.history.len(), 2);
+        assert_eq!(app.history[0].kind, EntryKind::Computation);
+        assert_eq!(app.history[1], HistoryEntry::assignment("x", "5"));
+        assert_eq!(app.history[1].to_string(), "x = 5");
+
+        app.recompute_last();
+        assert_eq!(app.display, "5");
+        assert_eq!(app.expression, "x =");
+
+        app.clear();
+        app.recall_variable("X");
+        assert_eq!(app.display, "5");
+        app.paste_expression("x * 2");
+        assert_eq!(app.display, "10");
+        assert_eq!(app.store_variable("Rate"), Ok(()));
+        assert_eq!(app.history.last().unwrap().to_string(), "rate = 10");
+        assert_eq!(app.evaluate("rate + x"), Ok(15.0));
+        assert_eq!(app.store_variable("2x"), Err(CalcError::InvalidInput));
+        assert_eq!(app.store_variable(""), Err(CalcError::InvalidInput));
+        for reserved in ["ans", "pi", "E", "phi"] {
+            assert_eq!(app.store_variable(reserved), Err(CalcError::InvalidInput), "{}", reserved);
+        }
+        assert_eq!(app.evaluate("pi"), Ok(std::f64::consts::PI));
+        assert!(is_variable_name("rate_2"));
     }
 
     #[test]
-    fn test_format_number_decimal() {
-        assert_eq!(format_number(3.14), "3.14");
-        assert_eq!(format_number(0.5), "0.5");
+    fn test_assignment_kind_survives_save() {
+        let path = std::env::temp_dir().join("calc_test_history_kinds.txt");
+        let history = vec![HistoryEntry::new("2 + 3", "5"), HistoryEntry::assignment("x", "5")];
+        save_history_to(&path, &history);
+        assert!(fs::read_to_string(&path).unwrap().ends_with("x\t5\tassign"));
+        assert_eq!(load_history_from(&path), history);
+        let _ = fs::remove_file(&path);
     }
 
     #[test]
-    fn test_format_number_trailing_zeros() {
-        assert_eq!(format_number(1.5000), "1.5");
-        assert_eq!(format_number(2.0), "2");
+    fn test_apply_unary_by_name() {
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.settings.persist_history = false;
+        type_sequence(&mut app, "9");
+        assert_eq!(app.apply_unary("sqrt"), Ok(()));
+        assert_eq!(app.display, "3");
+        assert_eq!(app.history.last().unwrap().expression, "\u{221A}(9)");
+
+        app.clear();
+        assert_eq!(app.apply_unary("sin"), Ok(()));
+        assert_eq!(app.display, "0");
+
+        type_sequence(&mut app, "4");
+        assert_eq!(app.apply_unary("bogus"), Err(CalcError::InvalidInput));
+        assert_eq!(app.display, "4");
+        for name in UNARY_FUNCTIONS {
+            assert!(app.clone().apply_unary(name).is_ok(), "{}", name);
+        }
     }
 
     #[test]
-    fn test_format_number_large_number() {
-        assert_eq!(format_number(1000000.0), "1000000");
+    fn test_fixed_decimals() {
+        assert_eq!(format_fixed(5.0, 2, RoundingMode::HalfEven), "5.00");
+        assert_eq!(format_fixed(3.1, 2, RoundingMode::HalfEven), "3.10");
+        assert_eq!(format_fixed(2.345, 2, RoundingMode::Down), "2.34");
+        assert_eq!(format_fixed(-0.001, 2, RoundingMode::HalfEven), "0.00");
+        assert_eq!(format_fixed(7.0, 0, RoundingMode::HalfEven), "7");
+
+        let mut app = CalcApp::new();
+        app.history.clear();
+        app.settings.persist_history = false;
+        app.settings.fixed_decimals = Some(2);
+        type_sequence(&mut app, "2+3=");
+        assert_eq!(app.display, "5.00");
+        type_sequence(&mut app, "+0.1=");
+        assert_eq!(app.display, "5.10");
+        assert_eq!(app.history.last().unwrap().to_string(), "5.00 + 0.10 = 5.10");
     }
 
     #[test]
-    fn test_chained_operations() {
+    fn test_use_history_result() {
         let mut app = CalcApp::new();
-        // 2 + 3 * 4 should compute left-to-right as (2 + 3) * 4 = 20
-        app.input_digit('2');
-        app.input_operator('+');
-        app.input_digit('3');
-        app.compute(); // 2 + 3 = 5
-        assert_eq!(app.display, "5");
+        app.settings.persist_history = false;
+        app.history = vec![
+            HistoryEntry::new("6 \u{00D7} 7", "42"),
+            HistoryEntry::new("1 / 0", "Error"),
+            HistoryEntry::new("1 / 3", "1/3"),
+        ];
 
-        app.input_operator('*');
-        app.input_digit('4');
-        app.compute(); // 5 * 4 = 20
-        assert_eq!(app.display, "20");
+        assert!(app.use_history_result(0));
+        assert_eq!(app.display, "42");
+        type_sequence(&mut app, "+1=");
+        assert_eq!(app.display, "43");
+
+        // As the second operand of a pending operation
+        type_sequence(&mut app, "2*");
+        assert!(app.use_history_result(0));
+        app.compute();
+        assert_eq!(app.display, "84");
+
+        let before = app.display.clone();
+        assert!(!app.use_history_result(1));
+        assert!(!app.use_history_result(99));
+        assert_eq!(app.display, before);
+
+        assert!(app.use_history_result(2));
+        assert_eq!(app.display, "0.3333333333");
+
+        // A recalled second operand is replaced by typing, not extended
+        type_sequence(&mut app, "C5+");
+        assert!(app.use_history_result(0));
+        type_sequence(&mut app, "1=");
+        assert_eq!(app.display, "6");
+
+        // Hex-mode results are read as hex
+        app.clear();
+        app.base = Base::Hex;
+        app.history = vec![HistoryEntry::new("F0 + F", "FF"), HistoryEntry::new("8 + 8", "10")];
+        assert!(app.use_history_result(0));
+        assert_eq!(app.current_value(), Some(255.0));
+        assert!(app.use_history_result(1));
+        assert_eq!(app.current_value(), Some(16.0));
+    }
+
+    #[test]
+    fn test_import_history_csv_kinds() {
+        let path = std::env::temp_dir().join("calc_test_import_kinds.csv");
+        fs::write(&path, "expression,result,kind\n2 + 3,5,\nx,5,assign\n1 + 1,2\ny,1,other\n").unwrap();
+        let mut app = CalcApp::new();
+        app.settings.persist_history = false;
+        let skipped = app.import_history_csv(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(skipped, 1);
+        assert_eq!(
+            app.history,
+            [HistoryEntry::new("2 + 3", "5"), HistoryEntry::assignment("x", "5"), HistoryEntry::new("1 + 1", "2")]
+        );
+    }
+
+    #[test]
+    fn test_reformat_history_follows_format() {
+        let mut app = CalcApp::new();
+        app.settings.persist_history = false;
+        app.history = vec![HistoryEntry::new("2 + 3", "5"), HistoryEntry::new("1 / 4", "0.25")];
+        app.settings.fixed_decimals = Some(2);
+        app.reformat_history();
+        assert_eq!(app.history[0].result, "5.00");
+
+        app.settings.notation = Notation::Scientific;
+        app.reformat_history();
+        assert_eq!(app.history[0].result, app.format(5.0));
+        assert_eq!(app.history[0].result, "5e0");
+        assert_eq!(app.history[1].result, "2.5e-1");
     }
 }
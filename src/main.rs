@@ -3,27 +3,41 @@
 use eframe::egui;
 use eframe::egui::{Color32, Pos2, Stroke};
 use calculator::CalcApp as LibCalcApp;
-use calculator::save_history;
 use rodio::{OutputStream, Sink, Decoder};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 const CALC_WIDTH: f32 = 320.0;
-const HISTORY_WIDTH: f32 = 230.0;
+/// The history panel grows with its longest entry between these widths
+const HISTORY_MIN_WIDTH: f32 = 230.0;
+const HISTORY_MAX_WIDTH: f32 = 480.0;
+/// Rough width of one character in a history entry, including the panel's
+/// margins spread over a typical line
+const HISTORY_CHAR_PX: f32 = 10.0;
 const WINDOW_HEIGHT: f32 = 500.0;
-const BLAZE_DURATION: f32 = 20.0;
+/// Longer expressions are shortened with a middle ellipsis
+const EXPRESSION_MAX_CHARS: usize = 36;
 
 /// Embedded 420 audio clip (first 20 seconds)
 const BLAZE_AUDIO: &[u8] = include_bytes!("../assets/blaze_mono.wav");
 
-/// Play the embedded audio clip on a background thread
-fn play_blaze_melody() {
-    std::thread::spawn(|| {
+/// Play the embedded audio clip on a background thread. Setting the
+/// returned flag stops it.
+fn play_blaze_melody() -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&stop);
+    std::thread::spawn(move || {
         let Ok((_stream, handle)) = OutputStream::try_default() else { return };
         let Ok(sink) = Sink::try_new(&handle) else { return };
         let cursor = std::io::Cursor::new(BLAZE_AUDIO);
         let Ok(source) = Decoder::new(cursor) else { return };
         sink.append(source);
-        sink.sleep_until_end();
+        while !sink.empty() && !flag.load(Ordering::Relaxed) {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        sink.stop();
     });
+    stop
 }
 
 /// Convert HSV (h: 0-360, s: 0-1, v: 0-1) to Color32
@@ -116,7 +130,7 @@ fn draw_leaflet_vein(
 }
 
 /// Draw the 420 Easter egg: rainbow background + realistic cannabis leaf + smoke
-fn draw_blaze_overlay(ui: &mut egui::Ui, t: f32) {
+fn draw_blaze_overlay(ui: &mut egui::Ui, t: f32, duration: f32) {
     let rect = ui.max_rect();
     let painter = ui.painter();
     let pi = std::f32::consts::PI;
@@ -124,8 +138,8 @@ fn draw_blaze_overlay(ui: &mut egui::Ui, t: f32) {
     // Fade: ramp up 0.5s, hold, fade out last 1.5s
     let alpha = if t < 0.5 {
         t / 0.5
-    } else if t > BLAZE_DURATION - 1.5 {
-        (BLAZE_DURATION - t) / 1.5
+    } else if t > duration - 1.5 {
+        (duration - t) / 1.5
     } else {
         1.0
     }.clamp(0.0, 1.0);
@@ -263,12 +277,18 @@ fn draw_blaze_overlay(ui: &mut egui::Ui, t: f32) {
 // Wrapper type to implement eframe::App for CalcApp
 struct CalcApp {
     inner: LibCalcApp,
+    /// Name typed in the Vars menu
+    var_name: String,
+    /// Stops the 420 melody while it plays
+    blaze_sound_stop: Option<Arc<AtomicBool>>,
 }
 
 impl CalcApp {
     fn new() -> Self {
         Self {
             inner: LibCalcApp::new(),
+            var_name: String::new(),
+            blaze_sound_stop: None,
         }
     }
 }
@@ -289,6 +309,13 @@ fn main() -> eframe::Result {
 }
 
 impl eframe::App for CalcApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // With autosave off, history is only written here
+        if !self.inner.settings.autosave {
+            self.inner.flush_history();
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let bg = egui::Color32::from_rgb(32, 32, 32);
         let num_bg = egui::Color32::from_rgb(59, 59, 59);
@@ -300,19 +327,39 @@ impl eframe::App for CalcApp {
         let history_bg = egui::Color32::from_rgb(40, 40, 40);
         let divider_color = egui::Color32::from_rgb(55, 55, 55);
 
+        // Ctrl+V enters a pasted number or evaluates a pasted expression
+        let pasted = ctx.input(|i| {
+            i.events.iter().find_map(|event| match event {
+                egui::Event::Paste(text) => Some(text.clone()),
+                _ => None,
+            })
+        });
+        if let Some(text) = pasted {
+            self.inner.paste(&text);
+        }
+
         // --- 420 Easter egg animation ---
+        // Any click or key press dismisses it
+        if self.inner.blaze_it
+            && ctx.input(|i| {
+                i.pointer.any_pressed()
+                    || i.events.iter().any(|e| matches!(e, egui::Event::Key { pressed: true, .. }))
+            })
+        {
+            self.inner.cancel_blaze();
+        }
         let blaze_t = if self.inner.blaze_it {
             if let Some(start) = self.inner.blaze_start {
                 // Play the melody once on first frame
                 if !self.inner.blaze_sound_played {
                     self.inner.blaze_sound_played = true;
-                    play_blaze_melody();
+                    if self.inner.settings.sound {
+                        self.blaze_sound_stop = Some(play_blaze_melody());
+                    }
                 }
                 let elapsed = start.elapsed().as_secs_f32();
-                if elapsed > BLAZE_DURATION {
-                    self.inner.blaze_it = false;
-                    self.inner.blaze_start = None;
-                    self.inner.blaze_sound_played = false;
+                if elapsed > self.inner.settings.blaze_duration {
+                    self.inner.cancel_blaze();
                     None
                 } else {
                     ctx.request_repaint();
@@ -324,10 +371,23 @@ impl eframe::App for CalcApp {
         } else {
             None
         };
+        // However the animation ended (a key, `cancel_blaze`, its time
+        // running out), the melody ends with it
+        if !self.inner.blaze_it {
+            if let Some(stop) = self.blaze_sound_stop.take() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
 
         // --- Right side panel: History (collapsible) ---
         if self.inner.show_history {
-            let target_w = CALC_WIDTH + HISTORY_WIDTH;
+            let history_width = calculator::history_panel_width(
+                &self.inner.history,
+                HISTORY_MIN_WIDTH,
+                HISTORY_MAX_WIDTH,
+                HISTORY_CHAR_PX,
+            );
+            let target_w = CALC_WIDTH + history_width;
             ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(target_w, WINDOW_HEIGHT)));
 
             let side_frame = egui::Frame::default()
@@ -335,7 +395,7 @@ impl eframe::App for CalcApp {
                 .inner_margin(egui::Margin::same(8.0));
 
             egui::SidePanel::right("history_panel")
-                .exact_width(HISTORY_WIDTH - 10.0)
+                .exact_width(history_width - 10.0)
                 .frame(side_frame)
                 .show(ctx, |ui| {
                     ui.horizontal(|ui| {
@@ -352,15 +412,23 @@ impl eframe::App for CalcApp {
                             ).clicked() {
                                 self.inner.show_history = false;
                             }
-                            if !self.inner.history.is_empty() {
-                                if ui.add(
+                            if !self.inner.history.is_empty()
+                                && ui.add(
                                     egui::Button::new(egui::RichText::new("Clear").size(12.0).color(text_gray))
                                         .fill(op_bg)
                                         .rounding(4.0),
-                                ).clicked() {
-                                    self.inner.history.clear();
-                                    save_history(&self.inner.history);
-                                }
+                                ).clicked()
+                            {
+                                self.inner.clear_history();
+                            }
+                            if self.inner.last_cleared_history.is_some()
+                                && ui.add(
+                                    egui::Button::new(egui::RichText::new("Undo clear").size(12.0).color(text_gray))
+                                        .fill(op_bg)
+                                        .rounding(4.0),
+                                ).clicked()
+                            {
+                                self.inner.restore_cleared_history();
                             }
                         });
                     });
@@ -390,13 +458,29 @@ impl eframe::App for CalcApp {
                             .auto_shrink([false, false])
                             .stick_to_bottom(true)
                             .show(ui, |ui| {
-                                for entry in self.inner.history.iter().rev() {
-                                    egui::Frame::default()
+                                let mut use_result = None;
+                                for (index, entry) in self.inner.history.iter().enumerate().rev() {
+                                    let card = egui::Frame::default()
                                         .fill(history_bg)
                                         .rounding(4.0)
                                         .inner_margin(egui::Margin::same(8.0))
                                         .show(ui, |ui| {
                                             ui.set_width(ui.available_width());
+                                            // Variable stores read as one `x = 5` line
+                                            if entry.kind == calculator::EntryKind::Assignment {
+                                                ui.with_layout(
+                                                    egui::Layout::right_to_left(egui::Align::Min),
+                                                    |ui| {
+                                                        ui.label(
+                                                            egui::RichText::new(entry.to_string())
+                                                                .size(14.0)
+                                                                .color(text_gray)
+                                                                .italics(),
+                                                        );
+                                                    },
+                                                );
+                                                return;
+                                            }
                                             ui.with_layout(
                                                 egui::Layout::right_to_left(egui::Align::Min),
                                                 |ui| {
@@ -422,8 +506,22 @@ impl eframe::App for CalcApp {
                                                 },
                                             );
                                         });
+                                    card.response.interact(egui::Sense::click()).context_menu(|ui| {
+                                        if ui.button("Use result").clicked() {
+                                            use_result = Some(index);
+                                            ui.close_menu();
+                                        }
+                                        if ui.button("Copy as LaTeX").clicked() {
+                                            ui.ctx().copy_text(calculator::to_latex(entry));
+                                            ui.close_menu();
+                                        }
+                                    });
                                     ui.add_space(2.0);
                                 }
+                                // Applied after the loop, which borrows the history
+                                if let Some(index) = use_result {
+                                    self.inner.use_history_result(index);
+                                }
                             });
                     }
                 });
@@ -439,43 +537,211 @@ impl eframe::App for CalcApp {
         egui::CentralPanel::default().frame(panel_frame).show(ctx, |ui| {
             // Display area
             ui.vertical(|ui| {
-                // History toggle button row
-                ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                    let label = if self.inner.show_history { "History <<" } else { "History >>" };
-                    if ui.add(
-                        egui::Button::new(
-                            egui::RichText::new(label).size(12.0).color(text_gray),
-                        )
-                        .fill(egui::Color32::TRANSPARENT),
-                    ).clicked() {
-                        self.inner.show_history = !self.inner.show_history;
-                    }
+                // History toggle button row, with the layout, constants and
+                // functions menus on the left
+                ui.horizontal(|ui| {
+                    ui.menu_button(egui::RichText::new("View").size(12.0).color(text_gray), |ui| {
+                        let current = self.inner.settings.layout;
+                        for layout in calculator::Layout::ALL {
+                            if ui.radio(current == layout, layout.label()).clicked() {
+                                self.inner.set_layout(layout);
+                                ui.close_menu();
+                            }
+                        }
+                        ui.separator();
+                        let current = self.inner.settings.notation;
+                        for notation in calculator::Notation::ALL {
+                            if ui.radio(current == notation, notation.label()).clicked() {
+                                self.inner.set_notation(notation);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    ui.menu_button(egui::RichText::new("Const").size(12.0).color(text_gray), |ui| {
+                        for c in calculator::Constant::ALL {
+                            let label = format!("{}  {}", c.symbol(), calculator::format_number(c.value()));
+                            if ui.button(label).clicked() {
+                                self.inner.insert_constant(c);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    ui.menu_button(egui::RichText::new("Vars").size(12.0).color(text_gray), |ui| {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(&mut self.var_name).desired_width(60.0));
+                            let name = self.var_name.trim().to_string();
+                            if ui
+                                .add_enabled(calculator::is_variable_name(&name), egui::Button::new("Store"))
+                                .clicked()
+                                && self.inner.store_variable(&name).is_ok()
+                            {
+                                self.var_name.clear();
+                                ui.close_menu();
+                            }
+                        });
+                        let variables: Vec<(String, f64)> =
+                            self.inner.variables.iter().map(|(k, v)| (k.clone(), *v)).collect();
+                        if !variables.is_empty() {
+                            ui.separator();
+                        }
+                        for (name, value) in variables {
+                            if ui.button(format!("{} = {}", name, calculator::format_number(value))).clicked() {
+                                self.inner.recall_variable(&name);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    // Extra functions; two-operand ones take the second value next
+                    ui.menu_button(egui::RichText::new("Ops").size(12.0).color(text_gray), |ui| {
+                        if ui.button("e\u{02E3}").clicked() {
+                            self.inner.exp();
+                            ui.close_menu();
+                        }
+                        if ui.button("10\u{02E3}").clicked() {
+                            self.inner.pow10();
+                            ui.close_menu();
+                        }
+                        if ui.button("ln").clicked() {
+                            self.inner.ln();
+                            ui.close_menu();
+                        }
+                        if ui.button("log").clicked() {
+                            self.inner.log10();
+                            ui.close_menu();
+                        }
+                        if ui.button("Degrees \u{2192} radians").clicked() {
+                            self.inner.deg_to_rad();
+                            ui.close_menu();
+                        }
+                        if ui.button("Radians \u{2192} degrees").clicked() {
+                            self.inner.rad_to_deg();
+                            ui.close_menu();
+                        }
+                        ui.separator();
+                        // INV stays in the menu so the function can be picked next
+                        ui.toggle_value(&mut self.inner.inverse_pending, "INV");
+                        let inv = if self.inner.inverse_pending { "\u{207B}\u{00B9}" } else { "" };
+                        if ui.button(format!("sin{}", inv)).clicked() {
+                            self.inner.sin();
+                            ui.close_menu();
+                        }
+                        if ui.button(format!("cos{}", inv)).clicked() {
+                            self.inner.cos();
+                            ui.close_menu();
+                        }
+                        if ui.button(format!("tan{}", inv)).clicked() {
+                            self.inner.tan();
+                            ui.close_menu();
+                        }
+                        ui.separator();
+                        let ops = [
+                            ("x\u{02B8}  Power", '^'),
+                            ("min(x, y)", calculator::OP_MIN),
+                            ("max(x, y)", calculator::OP_MAX),
+                            ("Round x to a multiple of y", calculator::OP_ROUND_TO),
+                        ];
+                        for (label, op) in ops {
+                            if ui.button(label).clicked() {
+                                self.inner.input_operator(op);
+                                ui.close_menu();
+                            }
+                        }
+                        ui.separator();
+                        ui.toggle_value(&mut self.inner.constant_mode, "K  (= repeats the last operation)");
+                        if self.inner.recording_macro {
+                            if ui.button("\u{25A0}  Stop recording").clicked() {
+                                self.inner.stop_macro();
+                                ui.close_menu();
+                            }
+                        } else if ui.button("\u{25CF}  Record macro").clicked() {
+                            self.inner.record_macro();
+                            ui.close_menu();
+                        }
+                        if ui
+                            .add_enabled(
+                                !self.inner.macro_steps.is_empty() && !self.inner.recording_macro,
+                                egui::Button::new("\u{25B6}  Replay macro"),
+                            )
+                            .clicked()
+                        {
+                            self.inner.replay_macro();
+                            ui.close_menu();
+                        }
+                    });
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                        let label = if self.inner.show_history { "History <<" } else { "History >>" };
+                        if ui.add(
+                            egui::Button::new(
+                                egui::RichText::new(label).size(12.0).color(text_gray),
+                            )
+                            .fill(egui::Color32::TRANSPARENT),
+                        ).clicked() {
+                            self.inner.show_history = !self.inner.show_history;
+                        }
+                    });
                 });
 
                 // Expression line (right-aligned)
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
                     ui.label(
-                        egui::RichText::new(&self.inner.expression)
+                        egui::RichText::new(self.inner.truncated_expression(EXPRESSION_MAX_CHARS))
                             .size(14.0)
                             .color(text_gray),
                     );
                 });
 
                 // Main display (right-aligned, large)
-                let display_size = if self.inner.display.len() > 12 {
-                    24.0
-                } else if self.inner.display.len() > 8 {
-                    32.0
-                } else {
-                    46.0
-                };
+                let display_text = self.inner.grouped_display();
+                let display_size = calculator::display_font_size(display_text.len());
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                    if self.inner.percentage_form {
+                        ui.label(egui::RichText::new("%").size(display_size * 0.6).color(text_gray));
+                    }
                     ui.label(
-                        egui::RichText::new(&self.inner.display)
+                        egui::RichText::new(display_text)
                             .size(display_size)
                             .color(text_white)
                             .strong(),
-                    );
+                    )
+                    .on_hover_text(self.inner.display_full_precision());
+                    // Marks a result that was rounded to fit, e.g. 1/3
+                    if self.inner.is_result() && self.inner.last_result_rounded() {
+                        ui.label(egui::RichText::new("\u{2248}").size(display_size * 0.6).color(text_gray));
+                    }
+                });
+
+                // Memory registers: click recalls, right-click for M+ / MC
+                let memory = self.inner.memory_values();
+                ui.horizontal(|ui| {
+                    let slot_w = (ui.available_width() - 3.0 * ui.spacing().item_spacing.x) / 4.0;
+                    for (slot, value) in memory.iter().enumerate() {
+                        let color = if *value != 0.0 { text_white } else { text_gray };
+                        let response = ui.add(
+                            egui::Button::new(
+                                egui::RichText::new(format!("M{}", slot + 1)).size(12.0).color(color),
+                            )
+                            .fill(egui::Color32::TRANSPARENT)
+                            .min_size(egui::vec2(slot_w, 20.0)),
+                        )
+                        .on_hover_text(format!("M{} = {}", slot + 1, calculator::format_number(*value)));
+                        if response.clicked() {
+                            self.inner.memory_recall(slot);
+                        }
+                        response.context_menu(|ui| {
+                            if ui.button("M+  Add display").clicked() {
+                                self.inner.memory_add(slot);
+                                ui.close_menu();
+                            }
+                            if ui.button("MS  Swap with display").clicked() {
+                                self.inner.memory_swap(slot);
+                                ui.close_menu();
+                            }
+                            if ui.button("MC  Clear").clicked() {
+                                self.inner.memory_clear(slot);
+                                ui.close_menu();
+                            }
+                        });
+                    }
                 });
 
                 ui.add_space(8.0);
@@ -485,8 +751,14 @@ impl eframe::App for CalcApp {
             let spacing = 2.0;
             ui.spacing_mut().item_spacing = egui::vec2(spacing, spacing);
 
+            let layout = self.inner.settings.layout;
+            let rows = match layout {
+                calculator::Layout::Basic => 6.0,
+                calculator::Layout::Scientific => 7.0,
+                calculator::Layout::Programmer => 8.0,
+            };
             let btn_w = (ui.available_width() - 3.0 * spacing) / 4.0;
-            let btn_h = (ui.available_height() - 5.0 * spacing) / 6.0;
+            let btn_h = (ui.available_height() - (rows - 1.0) * spacing) / rows;
             let btn = egui::vec2(btn_w, btn_h);
             let font_size = 20.0;
 
@@ -507,9 +779,70 @@ impl eframe::App for CalcApp {
                 .clicked()
             };
 
+            // Scientific: eˣ  10ˣ  EXP  π
+            if layout == calculator::Layout::Scientific {
+                ui.horizontal(|ui| {
+                    if make_btn(ui, "e\u{02E3}", btn, op_bg, text_white) { self.inner.exp(); }
+                    if make_btn(ui, "10\u{02E3}", btn, op_bg, text_white) { self.inner.pow10(); }
+                    if make_btn(ui, "EXP", btn, op_bg, text_white) { self.inner.input_exponent(); }
+                    if make_btn(ui, "\u{03C0}", btn, op_bg, text_white) {
+                        self.inner.insert_constant(calculator::Constant::Pi);
+                    }
+                });
+            }
+
+            // Programmer: hex digits A-F and the DEC / HEX toggle. Hex digits
+            // are dimmed (and ignored) while in decimal.
+            if layout == calculator::Layout::Programmer {
+                let hex = self.inner.base == calculator::Base::Hex;
+                let hex_color = if hex { text_white } else { text_gray };
+                ui.horizontal(|ui| {
+                    for d in ['A', 'B', 'C', 'D'] {
+                        if make_btn(ui, &d.to_string(), btn, num_bg, hex_color) { self.inner.input_hex_digit(d); }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    for d in ['E', 'F'] {
+                        if make_btn(ui, &d.to_string(), btn, num_bg, hex_color) { self.inner.input_hex_digit(d); }
+                    }
+                    let (dec_color, hex_label_color) = if hex { (text_gray, text_white) } else { (text_white, text_gray) };
+                    if make_btn(ui, "DEC", btn, op_bg, dec_color) { self.inner.set_base(calculator::Base::Decimal); }
+                    // Only whole numbers convert to hex
+                    ui.add_enabled_ui(hex || self.inner.display_is_integer(), |ui| {
+                        if make_btn(ui, "HEX", btn, op_bg, hex_label_color) { self.inner.set_base(calculator::Base::Hex); }
+                    });
+                });
+            }
+
             // Row 1: %  CE  C  DEL
             ui.horizontal(|ui| {
-                if make_btn(ui, "%", btn, op_bg, text_white) { self.inner.percent(); }
+                // Right-click % for markup / markdown by a percentage
+                let percent = ui.add(
+                    egui::Button::new(egui::RichText::new("%").size(font_size).color(text_white))
+                        .fill(op_bg)
+                        .rounding(4.0)
+                        .min_size(btn),
+                );
+                if percent.clicked() { self.inner.percent(); }
+                percent.context_menu(|ui| {
+                    if ui.button("+%  Increase by").clicked() {
+                        self.inner.input_operator(calculator::OP_PERCENT_UP);
+                        ui.close_menu();
+                    }
+                    if ui.button("\u{2212}%  Decrease by").clicked() {
+                        self.inner.input_operator(calculator::OP_PERCENT_DOWN);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("As percentage (\u{00D7}100)").clicked() {
+                        self.inner.as_percentage();
+                        ui.close_menu();
+                    }
+                    if ui.button("As decimal (\u{00F7}100)").clicked() {
+                        self.inner.as_decimal();
+                        ui.close_menu();
+                    }
+                });
                 if make_btn(ui, "CE", btn, op_bg, text_white) { self.inner.clear_entry(); }
                 if make_btn(ui, "C", btn, op_bg, text_white) { self.inner.clear(); }
                 if make_btn(ui, "DEL", btn, op_bg, text_white) { self.inner.backspace(); }
@@ -517,31 +850,8 @@ impl eframe::App for CalcApp {
 
             // Row 2: 1/x  x²  √x  ÷
             ui.horizontal(|ui| {
-                if make_btn(ui, "1/x", btn, op_bg, text_white) {
-                    if let Ok(val) = self.inner.display.parse::<f64>() {
-                        if val == 0.0 {
-                            self.inner.display = "Error".to_string();
-                            self.inner.expression = "Cannot divide by zero".to_string();
-                        } else {
-                            let result = calculator::format_number(1.0 / val);
-                            let expr = format!("1/({})", calculator::format_number(val));
-                            self.inner.add_history(expr.clone(), result.clone());
-                            self.inner.expression = expr;
-                            self.inner.display = result;
-                            self.inner.just_computed = true;
-                        }
-                    }
-                }
-                if make_btn(ui, "x\u{00B2}", btn, op_bg, text_white) {
-                    if let Ok(val) = self.inner.display.parse::<f64>() {
-                        let result = calculator::format_number(val * val);
-                        let expr = format!("sqr({})", calculator::format_number(val));
-                        self.inner.add_history(expr.clone(), result.clone());
-                        self.inner.expression = expr;
-                        self.inner.display = result;
-                        self.inner.just_computed = true;
-                    }
-                }
+                if make_btn(ui, "1/x", btn, op_bg, text_white) { self.inner.reciprocal(); }
+                if make_btn(ui, "x\u{00B2}", btn, op_bg, text_white) { self.inner.square(); }
                 // Square root button with proper math notation: small raised ² + √x
                 let sqrt_clicked = {
                     let mut job = egui::text::LayoutJob::default();
@@ -563,21 +873,7 @@ impl eframe::App for CalcApp {
                             .min_size(btn),
                     ).clicked()
                 };
-                if sqrt_clicked {
-                    if let Ok(val) = self.inner.display.parse::<f64>() {
-                        if val < 0.0 {
-                            self.inner.display = "Error".to_string();
-                            self.inner.expression = "Invalid input".to_string();
-                        } else {
-                            let result = calculator::format_number(val.sqrt());
-                            let expr = format!("\u{221A}({})", calculator::format_number(val));
-                            self.inner.add_history(expr.clone(), result.clone());
-                            self.inner.expression = expr;
-                            self.inner.display = result;
-                            self.inner.just_computed = true;
-                        }
-                    }
-                }
+                if sqrt_clicked { self.inner.square_root(); }
                 if make_btn(ui, "\u{00F7}", btn, op_bg, text_white) { self.inner.input_operator('/'); }
             });
 
@@ -607,15 +903,29 @@ impl eframe::App for CalcApp {
 
             // Row 6: ±  0  .  =
             ui.horizontal(|ui| {
-                if make_btn(ui, "+/-", btn, op_bg, text_white) { self.inner.toggle_sign(); }
+                // Result-aware: negates a whole result, or the entry being typed
+                if make_btn(ui, "+/-", btn, op_bg, text_white) { self.inner.negate_result(); }
                 if make_btn(ui, "0", btn, num_bg, text_white) { self.inner.input_digit('0'); }
                 if make_btn(ui, ".", btn, num_bg, text_white) { self.inner.input_dot(); }
-                if make_btn(ui, "=", btn, eq_bg, text_dark) { self.inner.compute(); }
+                // Right-click = to swap the operands of a pending operation
+                let equals = ui.add(
+                    egui::Button::new(egui::RichText::new("=").size(font_size).color(text_dark))
+                        .fill(eq_bg)
+                        .rounding(4.0)
+                        .min_size(btn),
+                );
+                if equals.clicked() { self.inner.compute(); }
+                equals.context_menu(|ui| {
+                    if ui.button("\u{21C4}  Swap operands").clicked() {
+                        self.inner.swap_operands();
+                        ui.close_menu();
+                    }
+                });
             });
 
             // Draw 420 overlay on top of everything
             if let Some(t) = blaze_t {
-                draw_blaze_overlay(ui, t);
+                draw_blaze_overlay(ui, t, self.inner.settings.blaze_duration);
             }
         });
     }
@@ -0,0 +1,146 @@
+// Exact fractions for the optional rational mode
+use crate::CalcError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A fraction in lowest terms with a positive denominator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rational {
+    pub num: i64,
+    pub den: i64,
+}
+
+impl Rational {
+    /// `num / den` reduced to lowest terms
+    pub fn new(num: i64, den: i64) -> Result<Self, CalcError> {
+        Self::from_i128(num as i128, den as i128)
+    }
+
+    pub fn from_integer(n: i64) -> Self {
+        Rational { num: n, den: 1 }
+    }
+
+    /// Parse display text that is an integer ("-4") or a fraction ("2/3").
+    /// Decimals, exponents and values that don't fit in `i64` give `None`.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.split_once('/') {
+            Some((num, den)) => Self::new(num.parse().ok()?, den.parse().ok()?).ok(),
+            None => text.parse().ok().map(Self::from_integer),
+        }
+    }
+
+    pub fn is_integer(self) -> bool {
+        self.den == 1
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    pub fn checked_add(self, other: Self) -> Result<Self, CalcError> {
+        Self::from_i128(
+            self.num as i128 * other.den as i128 + other.num as i128 * self.den as i128,
+            self.den as i128 * other.den as i128,
+        )
+    }
+
+    pub fn checked_sub(self, other: Self) -> Result<Self, CalcError> {
+        Self::from_i128(
+            self.num as i128 * other.den as i128 - other.num as i128 * self.den as i128,
+            self.den as i128 * other.den as i128,
+        )
+    }
+
+    pub fn checked_mul(self, other: Self) -> Result<Self, CalcError> {
+        Self::from_i128(
+            self.num as i128 * other.num as i128,
+            self.den as i128 * other.den as i128,
+        )
+    }
+
+    pub fn checked_div(self, other: Self) -> Result<Self, CalcError> {
+        Self::from_i128(
+            self.num as i128 * other.den as i128,
+            self.den as i128 * other.num as i128,
+        )
+    }
+
+    /// Reduce and normalise the sign; fails if the result doesn't fit in `i64`
+    fn from_i128(num: i128, den: i128) -> Result<Self, CalcError> {
+        if den == 0 {
+            return Err(CalcError::DivisionByZero);
+        }
+        let g = gcd(num.unsigned_abs(), den.unsigned_abs()) as i128;
+        let sign = if den < 0 { -1 } else { 1 };
+        let (num, den) = (sign * num / g, sign * den / g);
+        match (i64::try_from(num), i64::try_from(den)) {
+            (Ok(num), Ok(den)) => Ok(Rational { num, den }),
+            _ => Err(CalcError::Overflow),
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+/// `apply_op` for fractions; only `+ - * /` are exact
+pub fn apply_rational_op(a: Rational, op: char, b: Rational) -> Result<Rational, CalcError> {
+    match op {
+        '+' => a.checked_add(b),
+        '-' => a.checked_sub(b),
+        '*' => a.checked_mul(b),
+        '/' => a.checked_div(b),
+        _ => Err(CalcError::UnknownOperator(op)),
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(num: i64, den: i64) -> Rational {
+        Rational::new(num, den).unwrap()
+    }
+
+    #[test]
+    fn test_rational_arithmetic() {
+        assert_eq!(r(1, 3).checked_add(r(1, 3)), Ok(r(2, 3)));
+        assert_eq!(r(1, 2).checked_mul(r(2, 3)), Ok(r(1, 3)));
+        assert_eq!(r(1, 2).checked_sub(r(3, 4)), Ok(r(-1, 4)));
+        assert_eq!(r(2, 3).checked_div(r(4, 9)), Ok(r(3, 2)));
+        assert_eq!(r(1, 2).checked_div(r(0, 1)), Err(CalcError::DivisionByZero));
+        assert_eq!(r(i64::MAX, 1).checked_add(r(1, 1)), Err(CalcError::Overflow));
+    }
+
+    #[test]
+    fn test_rational_reduction_and_display() {
+        assert_eq!(r(6, -8), Rational { num: -3, den: 4 });
+        assert_eq!(r(0, 5), Rational { num: 0, den: 1 });
+        assert_eq!(r(2, 3).to_string(), "2/3");
+        assert_eq!(r(4, 2).to_string(), "2");
+    }
+
+    #[test]
+    fn test_rational_parse() {
+        assert_eq!(Rational::parse("2/3"), Some(r(2, 3)));
+        assert_eq!(Rational::parse("-4"), Some(r(-4, 1)));
+        assert_eq!(Rational::parse("0.25"), None);
+        assert_eq!(Rational::parse("1e5"), None);
+        assert_eq!(Rational::parse("1/0"), None);
+        assert_eq!(Rational::parse("Error"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use calculator::CalcApp;
+use calculator::{type_sequence, CalcApp};
 
 #[test]
 fn test_basic_addition() {
@@ -14,13 +14,7 @@ fn test_basic_addition() {
 fn test_advanced_calculation() {
     let mut app = CalcApp::new();
     // Test: 100 - 25 = 75
-    app.input_digit('1');
-    app.input_digit('0');
-    app.input_digit('0');
-    app.input_operator('-');
-    app.input_digit('2');
-    app.input_digit('5');
-    app.compute();
+    type_sequence(&mut app, "100-25=");
     assert_eq!(app.display, "75");
 }
 
@@ -28,15 +22,9 @@ fn test_advanced_calculation() {
 fn test_multiple_operations() {
     let mut app = CalcApp::new();
     // Test: 10 + 5 - 3 = 12
-    app.input_digit('1');
-    app.input_digit('0');
-    app.input_operator('+');
-    app.input_digit('5');
-    app.compute();
+    type_sequence(&mut app, "10+5=");
     assert_eq!(app.display, "15");
-    
-    app.input_operator('-');
-    app.input_digit('3');
-    app.compute();
+
+    type_sequence(&mut app, "-3=");
     assert_eq!(app.display, "12");
 }